@@ -1,7 +1,7 @@
 use anyhow::Result;
 use clap::Parser;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use cli_common::{exit_with_error, open_input};
+use std::io::{self, BufRead, BufWriter, Write};
 
 /// Concatenate FILE(s) to standard output.
 /// With no FILE, or when FILE is -, read standard input.
@@ -12,6 +12,7 @@ struct Args {
     #[arg(value_name = "FILE", default_value = "-")]
     files: Vec<String>,
 
+    // The options -n and -b are mutually exclusive.
     /// Number all output lines
     #[arg(short = 'n', long, conflicts_with = "number_nonblank")]
     number: bool,
@@ -20,31 +21,58 @@ struct Args {
     #[arg(short = 'b', long)]
     number_nonblank: bool,
 
-    // The options -n and -b are mutually exclusive.
+    // The last of --restart-numbering and --continue-numbering wins.
+    /// Start the line numbers over at 1 for each file (default)
+    #[arg(long, overrides_with = "continue_numbering")]
+    restart_numbering: bool,
+
+    /// Keep counting the line numbers across files
+    #[arg(long, overrides_with = "restart_numbering")]
+    continue_numbering: bool,
+
+    /// Capacity of the output buffer in bytes
+    #[arg(
+        long,
+        value_name = "BYTES",
+        default_value_t = 64 * 1024,
+        value_parser = clap::value_parser!(u64).range(MIN_BUFFER_SIZE..),
+    )]
+    buffer_size: u64,
 }
 
+// A smaller buffer would only make for more write system calls.
+const MIN_BUFFER_SIZE: u64 = 512;
+
 fn main() -> Result<()> {
     let args = Args::parse();
 
+    // Lock STDOUT once and buffer the output so that each line does not cost a separate write
+    // system call.
+    let writer = BufWriter::with_capacity(args.buffer_size as usize, io::stdout().lock());
+
     // Catch the Err variant and print the error message to STDERR.
-    if let Err(e) = run(args) {
-        eprintln!("{e}");
-        std::process::exit(1);
+    if let Err(e) = run(args, writer) {
+        exit_with_error(e, 1);
     }
 
     Ok(())
 }
 
-// Run the program with parsed arguments.
-fn run(args: Args) -> Result<()> {
+// Run the program with parsed arguments, writing the output to the writer.
+fn run(args: Args, mut writer: impl Write) -> Result<()> {
+    // Initialize the line counter, which may carry over from one file to the next.
+    let mut line_count = 0;
+
     for filename in args.files {
-        match open_input_source(&filename) {
+        match open_input(&filename) {
             Err(e) => {
-                eprintln!("Failed to open {filename}: {e}")
+                eprintln!("Failed to open {e}")
             }
             Ok(file_content) => {
-                // Initialize the line counter for each file.
-                let mut line_count = 0;
+                // Reset the line counter for each file unless numbering continues across files.
+                if !args.continue_numbering {
+                    line_count = 0;
+                }
 
                 // Iterate through each line with index.
                 for line in file_content.lines() {
@@ -54,7 +82,7 @@ fn run(args: Args) -> Result<()> {
                     // Handle printing line numbers.
                     if args.number {
                         line_count += 1;
-                        println!("{line_count:>6}\t{line}");
+                        writeln!(writer, "{line_count:>6}\t{line}")?;
 
                         continue;
                     }
@@ -62,38 +90,135 @@ fn run(args: Args) -> Result<()> {
                     // Handle printing line numbers for non-blank lines.
                     if args.number_nonblank {
                         if line.is_empty() {
-                            // Print a blank line.
-                            println!();
+                            // Print a blank line, which does not consume a number.
+                            writeln!(writer)?;
                         } else {
                             line_count += 1;
-                            println!("{line_count:>6}\t{line}");
+                            writeln!(writer, "{line_count:>6}\t{line}")?;
                         }
 
                         continue;
                     }
 
                     // If there are no numbering options, just print the line.
-                    println!("{line}");
+                    writeln!(writer, "{line}")?;
                 }
             }
         }
     }
 
+    // Surface any error writing the buffered output.
+    writer.flush()?;
+
     Ok(())
 }
 
-// Accepts a filename and returns either an error or a boxed value that implements the BufRead
-// trait.
-// - The return type includes the dyn keyword to say that the return type's trait is dynamically
-// dispatched. This allows us to abstract the idea of the input source.
-// - The return type is placed into a Box. which is a way to store a value on the heap. The
-// compiler does not have enough information from dyn BufRead to know the size of the return type.
-// If a variable does not have a fixed known size, then Rust cannot store it on the stack. The
-// solution is to instead allocate memory on the heap by putting the return value into a Box, which
-// is a pointer with a known size.
-fn open_input_source(filename: &str) -> Result<Box<dyn BufRead>> {
-    match filename {
-        "-" => Ok(Box::new(BufReader::new(io::stdin()))),
-        _ => Ok(Box::new(BufReader::new(File::open(filename)?))),
+// Unit tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+
+    // Runs catr over two code fragments that contain blank lines.
+    fn run_fragments(flags: &[&str]) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        let fragment1 = dir.path().join("fragment1.rs");
+        let fragment2 = dir.path().join("fragment2.rs");
+        fs::write(&fragment1, "use std::io;\n\nfn a() {}\n").unwrap();
+        fs::write(&fragment2, "\nfn b() {}\n").unwrap();
+
+        let mut argv = vec!["catr".to_string()];
+        argv.extend(flags.iter().map(|flag| flag.to_string()));
+        argv.push(fragment1.display().to_string());
+        argv.push(fragment2.display().to_string());
+
+        let mut output = vec![];
+        run(Args::parse_from(argv), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    // A writer whose reader has gone away, like STDOUT piped into `head` after it exits.
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+    }
+
+    #[test]
+    fn test_broken_pipe() {
+        let dir = tempfile::tempdir().unwrap();
+        let filename = dir.path().join("input.txt");
+        fs::write(&filename, "a\nb\n").unwrap();
+        let args = Args::parse_from(["catr", filename.to_str().unwrap()]);
+
+        // The error tells main to exit quietly.
+        let error = run(args, BufWriter::with_capacity(1, ClosedPipe)).unwrap_err();
+        assert!(cli_common::is_broken_pipe(&error));
+    }
+
+    #[test]
+    fn test_number_nonblank_restart() {
+        let expected = "     1\tuse std::io;\n\n     2\tfn a() {}\n\n     1\tfn b() {}\n";
+        assert_eq!(run_fragments(&["-b"]), expected);
+        assert_eq!(run_fragments(&["-b", "--restart-numbering"]), expected);
+    }
+
+    #[test]
+    fn test_number_nonblank_continue() {
+        // The blank lines do not consume numbers across files either.
+        assert_eq!(
+            run_fragments(&["-b", "--continue-numbering"]),
+            "     1\tuse std::io;\n\n     2\tfn a() {}\n\n     3\tfn b() {}\n"
+        );
+
+        // The last of the two numbering choices wins.
+        assert_eq!(
+            run_fragments(&["-b", "--continue-numbering", "--restart-numbering"]),
+            "     1\tuse std::io;\n\n     2\tfn a() {}\n\n     1\tfn b() {}\n"
+        );
+    }
+
+    #[test]
+    fn test_number_continue() {
+        assert_eq!(
+            run_fragments(&["-n", "--continue-numbering"]),
+            "     1\tuse std::io;\n     2\t\n     3\tfn a() {}\n     4\t\n     5\tfn b() {}\n"
+        );
+    }
+
+    #[test]
+    fn test_buffer_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let filename = dir.path().join("lines.txt");
+        let text: String = (1..=1000).map(|n| format!("line {n}\n\n")).collect();
+        fs::write(&filename, &text).unwrap();
+        let filename = filename.display().to_string();
+
+        // Runs catr with the writer buffered the same way as in main.
+        let run_buffered = |buffer_size: &str| -> Vec<u8> {
+            let args = Args::parse_from(["catr", "-b", "--buffer-size", buffer_size, &filename]);
+            let mut output = vec![];
+            let writer = BufWriter::with_capacity(args.buffer_size as usize, &mut output);
+            run(args, writer).unwrap();
+            output
+        };
+
+        // The output is the same whatever the capacity of the buffer is.
+        let tiny = run_buffered("512");
+        assert_eq!(tiny, run_buffered("1048576"));
+        assert_eq!(tiny.len(), text.len() + 1000 * 7);
+
+        // The buffer has a minimum size.
+        assert!(Args::try_parse_from(["catr", "--buffer-size", "511"]).is_err());
+        assert!(Args::try_parse_from(["catr", "--buffer-size", "0"]).is_err());
+        assert_eq!(Args::parse_from(["catr"]).buffer_size, 65536);
     }
 }
@@ -0,0 +1,33 @@
+use anyhow::Result;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+
+const PRG: &str = "catr";
+
+#[test]
+fn broken_pipe() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let filename = dir.path().join("large.txt");
+    let contents: String = (0..200_000)
+        .map(|number| format!("{number:06}\n"))
+        .collect();
+    fs::write(&filename, contents)?;
+
+    // Stop reading after the first line, like `catr large.txt | head -n 1`.
+    let mut child = Command::new(assert_cmd::cargo::cargo_bin(PRG))
+        .arg(&filename)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let mut first_line = String::new();
+    BufReader::new(child.stdout.take().unwrap()).read_line(&mut first_line)?;
+    assert_eq!(first_line, "000000\n");
+
+    // The output pipe is closed once the reader above is dropped.
+    let output = child.wait_with_output()?;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stderr)?, "");
+    Ok(())
+}
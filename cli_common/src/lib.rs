@@ -0,0 +1,159 @@
+//! Helpers shared by the command-line tools.
+
+use anyhow::{anyhow, Result};
+use std::{
+    env,
+    fs::File,
+    io::{self, BufRead, BufReader, IsTerminal},
+};
+
+/// Opens a file for buffered reading, or STDIN when the path is "-". The error message starts with
+/// the path, like "missing.txt: No such file or directory (os error 2)", so that it can be printed
+/// as it is.
+pub fn open_input(path: &str) -> Result<Box<dyn BufRead>> {
+    match path {
+        "-" => Ok(Box::new(BufReader::new(io::stdin()))),
+        _ => Ok(Box::new(BufReader::new(
+            File::open(path).map_err(|e| anyhow!("{path}: {e}"))?,
+        ))),
+    }
+}
+
+/// Returns whether the error, or any error that caused it, is a write to a closed pipe. This happens
+/// when the output is piped into a command like `head` that exits before reading all of it.
+pub fn is_broken_pipe(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+    })
+}
+
+/// Prints the error, followed by its causes, to STDERR and exits with the status. Like coreutils,
+/// a broken pipe is not reported, and the program exits quietly with status 0 instead.
+pub fn exit_with_error(error: anyhow::Error, exit_code: i32) -> ! {
+    if is_broken_pipe(&error) {
+        std::process::exit(0);
+    }
+
+    eprintln!("{error:#}");
+    std::process::exit(exit_code);
+}
+
+/// Represents when to color the output, as the value of a `--color=WHEN` option.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// Only when STDOUT is a terminal and NO_COLOR is not set
+    Auto,
+    /// Even when the output is piped
+    Always,
+    /// Not at all
+    Never,
+}
+
+impl ColorChoice {
+    /// Returns whether to write ANSI color codes to STDOUT.
+    pub fn should_colorize(self) -> bool {
+        self.resolve(
+            io::stdout().is_terminal(),
+            env::var_os("NO_COLOR").is_some(),
+        )
+    }
+
+    // Follows the NO_COLOR convention only when the color is not asked for explicitly.
+    fn resolve(self, is_terminal: bool, has_no_color: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => is_terminal && !has_no_color,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_open_input_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("input.txt");
+        std::fs::write(&path, "hello\n").unwrap();
+
+        let mut content = String::new();
+        open_input(path.to_str().unwrap())
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "hello\n");
+    }
+
+    #[test]
+    fn test_open_input_stdin() {
+        // Opening STDIN does not read from it, so this does not block.
+        assert!(open_input("-").is_ok());
+    }
+
+    #[test]
+    fn test_open_input_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing.txt");
+        let path = path.to_str().unwrap();
+
+        // The message of the I/O error follows the path.
+        let io_error = File::open(path).unwrap_err();
+        let error = open_input(path).err().unwrap();
+        assert_eq!(error.to_string(), format!("{path}: {io_error}"));
+        assert!(error.to_string().ends_with("(os error 2)"));
+    }
+
+    #[test]
+    fn test_is_broken_pipe() {
+        let broken_pipe = || io::Error::from(io::ErrorKind::BrokenPipe);
+
+        assert!(is_broken_pipe(&anyhow::Error::from(broken_pipe())));
+        assert!(!is_broken_pipe(&anyhow::Error::from(io::Error::from(
+            io::ErrorKind::NotFound
+        ))));
+        assert!(!is_broken_pipe(&anyhow!("broken pipe")));
+
+        // The broken pipe can be the cause of another error.
+        let error = anyhow::Error::from(broken_pipe()).context("failed to write");
+        assert!(is_broken_pipe(&error));
+    }
+
+    #[test]
+    fn test_color_choice() {
+        // Each mode on a terminal, without and with NO_COLOR
+        assert!(ColorChoice::Auto.resolve(true, false));
+        assert!(!ColorChoice::Auto.resolve(true, true));
+        assert!(ColorChoice::Always.resolve(true, true));
+        assert!(!ColorChoice::Never.resolve(true, false));
+
+        // Only auto turns the color off when the output is not a terminal.
+        assert!(!ColorChoice::Auto.resolve(false, false));
+        assert!(ColorChoice::Always.resolve(false, false));
+        assert!(!ColorChoice::Never.resolve(false, false));
+
+        // The explicit choices do not depend on the environment.
+        assert!(!ColorChoice::Never.should_colorize());
+        assert!(ColorChoice::Always.should_colorize());
+    }
+
+    #[test]
+    fn test_color_choice_parse() {
+        use clap::ValueEnum;
+
+        assert_eq!(
+            ColorChoice::from_str("always", false),
+            Ok(ColorChoice::Always)
+        );
+        assert_eq!(ColorChoice::from_str("auto", false), Ok(ColorChoice::Auto));
+        assert_eq!(
+            ColorChoice::from_str("never", false),
+            Ok(ColorChoice::Never)
+        );
+        assert!(ColorChoice::from_str("sometimes", false).is_err());
+    }
+}
@@ -1,8 +1,8 @@
 use clap::{ArgAction, Parser};
+use cli_common::{exit_with_error, open_input};
 use std::{
     cmp::Ordering,
-    fs::File,
-    io::{self, BufRead, BufReader},
+    io::{self, BufRead, Write},
 };
 
 /// compare two sorted files line by line
@@ -13,11 +13,11 @@ struct CliArguments {
     //
     // - The order in which positional arguments are defined is important.
     //
-    /// Input file 1
+    /// Input file 1 (use "-" to read STDIN)
     #[arg()]
     file1: String,
 
-    /// Input file 2
+    /// Input file 2 (use "-" to read STDIN)
     #[arg()]
     file2: String,
 
@@ -41,12 +41,64 @@ struct CliArguments {
     #[arg(short, long)]
     ignore_case: bool,
 
+    /// Ignore spaces and tabs at the end of lines when comparing them
+    #[arg(long)]
+    ignore_trailing_whitespace: bool,
+
     /// Separate columns with DELIMITER
     #[arg(short, long = "output-delimiter", default_value = "\t")]
     delimiter: String,
+
+    /// Output a summary
+    #[arg(long)]
+    total: bool,
+
+    /// Print how many lines fell into each column to STDERR, with labels
+    #[arg(long)]
+    verbose: bool,
+
+    /// Check that the input is correctly sorted, and fail as soon as it is not
+    #[arg(long, conflicts_with = "nocheck_order")]
+    check_order: bool,
+
+    /// Do not check that the input is correctly sorted
+    #[arg(long)]
+    nocheck_order: bool,
+
+    /// Line delimiter is NUL, not newline
+    #[arg(short, long)]
+    zero_terminated: bool,
+
+    /// Compare lines that are both numbers by their numeric value, as sorted by `sort -n`
+    #[arg(long)]
+    numeric: bool,
+}
+
+// Represents a function that decides the order of two lines
+type Comparator = fn(&str, &str) -> Ordering;
+
+// Represents how the order of the input lines is checked
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OrderCheckMode {
+    // Warn about the first unsorted line of each file, then fail after printing everything
+    Warn,
+    // Fail as soon as an unsorted line is found
+    Strict,
+    // Do not check the order at all
+    Skip,
+}
+
+// Remembers the previous line of an input file to detect unsorted input
+struct OrderChecker {
+    file_number: u8,
+    mode: OrderCheckMode,
+    compare: Comparator,
+    previous_line: Option<String>,
+    is_disordered: bool,
 }
 
 // Represents the column where the value should be printed
+#[derive(Debug, PartialEq)]
 enum Column<'a> {
     Col1(&'a str),
     Col2(&'a str),
@@ -55,8 +107,7 @@ enum Column<'a> {
 
 fn main() {
     if let Err(e) = do_run(CliArguments::parse()) {
-        eprintln!("{e}");
-        std::process::exit(1);
+        exit_with_error(e, 1);
     }
 }
 
@@ -80,108 +131,188 @@ fn do_run(args: CliArguments) -> anyhow::Result<()> {
         }
     };
 
-    let print_column = |col: Column| {
-        let mut output_column_values = vec![];
+    // Records are separated by NUL instead of newline with --zero-terminated, in both the input
+    // and the output.
+    let record_terminator = if args.zero_terminated { "\0" } else { "\n" };
 
-        match col {
-            Column::Col1(text) => {
-                if args.show_col1 {
-                    output_column_values.push(text)
-                }
-            }
-            Column::Col2(text) => {
-                if args.show_col2 {
-                    if args.show_col1 {
-                        output_column_values.push(""); // fill col1 in with a spacer
-                    }
+    // Count the lines classified into each column, including the columns that are suppressed, so
+    // that the summary reflects all the lines compared.
+    let mut col1_count = 0;
+    let mut col2_count = 0;
+    let mut col3_count = 0;
 
-                    output_column_values.push(text);
-                }
-            }
-            Column::Col3(text) => {
-                if args.show_col3 {
-                    if args.show_col1 {
-                        output_column_values.push(""); // fill col1 in with a spacer
-                    }
+    let mut stdout = io::stdout().lock();
 
-                    if args.show_col2 {
-                        output_column_values.push(""); // fill col2 in with a spacer
-                    }
-
-                    output_column_values.push(text);
-                }
-            }
+    let mut print_column = |col: Column| -> io::Result<()> {
+        match col {
+            Column::Col1(_) => col1_count += 1,
+            Column::Col2(_) => col2_count += 1,
+            Column::Col3(_) => col3_count += 1,
         }
 
-        if !output_column_values.is_empty() {
-            println!("{}", output_column_values.join(&args.delimiter));
+        let show_columns = [args.show_col1, args.show_col2, args.show_col3];
+
+        match format_column(&col, show_columns, &args.delimiter) {
+            Some(row) => write!(stdout, "{row}{record_terminator}"),
+            None => Ok(()),
         }
     };
 
     // Attempt to open the two input files
-    let filehandle1 = open_input_file(file1)?;
-    let filehandle2 = open_input_file(file2)?;
+    let filehandle1 = open_input(file1)?;
+    let filehandle2 = open_input(file2)?;
     // println!(r#"Opened "{file1}" and "{file2}""#);
 
-    // Use BufRead::lines to read files as it is not necessary to preserve line endings.
-    // Create iterators, remove errors, then apply case-sensitivity to each line.
-    let mut lines1 = filehandle1.lines().map_while(Result::ok).map(apply_case);
-    let mut lines2 = filehandle2.lines().map_while(Result::ok).map(apply_case);
+    // Choose how to decide the order of two lines, both for the merge and for the order check.
+    // Trailing whitespace is only ignored in the comparison, so the lines are printed as they are,
+    // and a line common to both files is printed as it is in file 1.
+    let compare: Comparator = match (args.numeric, args.ignore_trailing_whitespace) {
+        (true, false) => compare_numeric,
+        (true, true) => |line1, line2| compare_numeric(trim_end(line1), trim_end(line2)),
+        (false, false) => |line1, line2| line1.cmp(line2),
+        (false, true) => |line1, line2| trim_end(line1).cmp(trim_end(line2)),
+    };
+
+    // Check the order of each file as its lines are read. The lines have already been downcased
+    // for --ignore-case, so the order is checked the same way the lines are compared.
+    let order_check_mode = if args.check_order {
+        OrderCheckMode::Strict
+    } else if args.nocheck_order {
+        OrderCheckMode::Skip
+    } else {
+        OrderCheckMode::Warn
+    };
+    let mut order_checker1 = OrderChecker::new(1, order_check_mode, compare);
+    let mut order_checker2 = OrderChecker::new(2, order_check_mode, compare);
+
+    // Create iterators over the records without their terminators, remove errors, then apply
+    // case-sensitivity to each line.
+    let lines1 = read_records(filehandle1, args.zero_terminated)
+        .map(apply_case)
+        .map(|line| order_checker1.check(&line).map(|_| line));
+    let lines2 = read_records(filehandle2, args.zero_terminated)
+        .map(apply_case)
+        .map(|line| order_checker2.check(&line).map(|_| line));
+
+    merge_lines(lines1, lines2, compare, &mut print_column)?;
+
+    // Print the summary line like GNU comm, separating the counts with the output delimiter.
+    if args.total {
+        let total_column_values = [
+            col1_count.to_string(),
+            col2_count.to_string(),
+            col3_count.to_string(),
+            String::from("total"),
+        ];
+
+        write!(
+            stdout,
+            "{}{record_terminator}",
+            total_column_values.join(&args.delimiter)
+        )?;
+    }
+
+    // Print the same counts as --total in a human-readable form, keeping STDOUT clean.
+    if args.verbose {
+        eprintln!("unique to {file1}: {col1_count}");
+        eprintln!("unique to {file2}: {col2_count}");
+        eprintln!("common to both: {col3_count}");
+    }
+
+    // Like GNU comm, fail after printing everything when an unsorted file was only warned about.
+    if order_checker1.is_disordered || order_checker2.is_disordered {
+        anyhow::bail!("comm: input is not in sorted order");
+    }
+
+    Ok(())
+}
 
-    // The Iterator::text method advances an iterator and returns the next value.
-    // Here it will retrieve the first line from a filehandle.
-    let mut line1 = lines1.next();
-    let mut line2 = lines2.next();
+// Formats a row with the text in its column, or returns None when the column is suppressed. Like
+// GNU comm, the text is preceded by one delimiter for each column before it that is shown, so
+// that the suppressed columns take no space.
+fn format_column(column: &Column, show_columns: [bool; 3], delimiter: &str) -> Option<String> {
+    let (column_index, text) = match column {
+        Column::Col1(text) => (0, text),
+        Column::Col2(text) => (1, text),
+        Column::Col3(text) => (2, text),
+    };
+
+    if !show_columns[column_index] {
+        return None;
+    }
+
+    // Fill the preceding columns in with spacers.
+    let spacer_count = show_columns[..column_index]
+        .iter()
+        .filter(|&&is_shown| is_shown)
+        .count();
+
+    Some(format!("{}{text}", delimiter.repeat(spacer_count)))
+}
+
+// Merges two sorted sequences of lines, passing each line to the callback with the column where it
+// belongs: only in the first sequence, only in the second, or in both. An error from either
+// sequence, such as an unsorted line, stops the merge.
+fn merge_lines(
+    mut lines1: impl Iterator<Item = anyhow::Result<String>>,
+    mut lines2: impl Iterator<Item = anyhow::Result<String>>,
+    compare: Comparator,
+    mut on_column: impl FnMut(Column) -> io::Result<()>,
+) -> anyhow::Result<()> {
+    // The Iterator::next method advances an iterator and returns the next value.
+    // Here it will retrieve the first line from each sequence.
+    let mut line1 = lines1.next().transpose()?;
+    let mut line2 = lines2.next().transpose()?;
 
     while line1.is_some() || line2.is_some() {
         // Compare all the possible combinations of the two line variables for two variants.
         match (&line1, &line2) {
             (Some(val1), Some(val2)) => {
-                // Use Ord::cmp to compare the first value to the second. This will return an enum variant of
+                // Compare the first value to the second. This will return an enum variant of
                 // std::cmp::Ordering.
-                match val1.cmp(val2) {
+                match compare(val1, val2) {
                     // When the two values are the same
                     Ordering::Equal => {
                         // print the value in column 3
-                        print_column(Column::Col3(val1));
+                        on_column(Column::Col3(val1))?;
 
-                        // get the values from each of the files
-                        line1 = lines1.next();
-                        line2 = lines2.next();
+                        // get the values from each of the sequences
+                        line1 = lines1.next().transpose()?;
+                        line2 = lines2.next().transpose()?;
                     }
                     // When the first value is less than the second
                     Ordering::Less => {
                         // print the first value in column 1
-                        print_column(Column::Col1(val1));
+                        on_column(Column::Col1(val1))?;
 
-                        // get the next value from the first file
-                        line1 = lines1.next();
+                        // get the next value from the first sequence
+                        line1 = lines1.next().transpose()?;
                     }
                     // When the first value is greater than the second
                     Ordering::Greater => {
                         // print the second value in column 2
-                        print_column(Column::Col2(val2));
+                        on_column(Column::Col2(val2))?;
 
-                        // get the next value from the second file
-                        line2 = lines2.next();
+                        // get the next value from the second sequence
+                        line2 = lines2.next().transpose()?;
                     }
                 }
             }
-            // When there is a value only from the first file
+            // When there is a value only from the first sequence
             (Some(val1), None) => {
                 // print the value in column 1
-                print_column(Column::Col1(val1));
+                on_column(Column::Col1(val1))?;
 
-                // get the next value from the first file
-                line1 = lines1.next();
+                // get the next value from the first sequence
+                line1 = lines1.next().transpose()?;
             }
-            // When there is a value only from the second file
+            // When there is a value only from the second sequence
             (None, Some(val2)) => {
                 // print the value in column 2
-                print_column(Column::Col2(val2));
+                on_column(Column::Col2(val2))?;
 
-                // get the next value from the second file
-                line2 = lines2.next();
+                // get the next value from the second sequence
+                line2 = lines2.next().transpose()?;
             }
             _ => (),
         };
@@ -190,15 +321,244 @@ fn do_run(args: CliArguments) -> anyhow::Result<()> {
     Ok(())
 }
 
-// Opening user-provided input source
-fn open_input_file(filename: &str) -> anyhow::Result<Box<dyn BufRead>> {
-    match filename {
-        "-" => Ok(Box::new(BufReader::new(io::stdin()))),
-        _ => {
-            // Incorporate the filename into the error message
-            Ok(Box::new(BufReader::new(
-                File::open(filename).map_err(|e| anyhow::anyhow!("{filename}: {e}"))?,
-            )))
+impl OrderChecker {
+    fn new(file_number: u8, mode: OrderCheckMode, compare: Comparator) -> Self {
+        Self {
+            file_number,
+            mode,
+            compare,
+            previous_line: None,
+            is_disordered: false,
+        }
+    }
+
+    // Verifies that a newly read line is not less than the previous line of the same file.
+    fn check(&mut self, line: &str) -> anyhow::Result<()> {
+        // Like GNU comm, stop checking a file once it is known to be unsorted.
+        if self.mode == OrderCheckMode::Skip || self.is_disordered {
+            return Ok(());
         }
+
+        if let Some(previous_line) = &self.previous_line {
+            if (self.compare)(line, previous_line) == Ordering::Less {
+                self.is_disordered = true;
+
+                let message = format!("comm: file {} is not in sorted order", self.file_number);
+
+                if self.mode == OrderCheckMode::Strict {
+                    anyhow::bail!(message);
+                }
+
+                eprintln!("{message}");
+            }
+        }
+
+        self.previous_line = Some(line.to_string());
+
+        Ok(())
+    }
+}
+
+// Removes the spaces and tabs at the end of a line.
+fn trim_end(line: &str) -> &str {
+    line.trim_end_matches([' ', '\t'])
+}
+
+// Compares two lines by their numeric value when both of them parse as numbers, and falls back
+// to comparing them as strings otherwise. Mixing numeric and non-numeric lines is best-effort:
+// the result only matches `sort -n` when each file sorts its numbers apart from other text.
+fn compare_numeric(line1: &str, line2: &str) -> Ordering {
+    let parse = |line: &str| line.trim().parse::<f64>().ok().filter(|n| n.is_finite());
+
+    match (parse(line1), parse(line2)) {
+        (Some(n1), Some(n2)) => n1.total_cmp(&n2),
+        _ => line1.cmp(line2),
+    }
+}
+
+// Splits the input into records without their terminators. Use BufRead::lines for newlines as it
+// is not necessary to preserve line endings, and BufRead::split for NUL-terminated records. Both
+// yield the final record even when it has no terminator.
+fn read_records(
+    filehandle: Box<dyn BufRead>,
+    zero_terminated: bool,
+) -> Box<dyn Iterator<Item = String>> {
+    if zero_terminated {
+        Box::new(
+            filehandle
+                .split(b'\0')
+                .map_while(Result::ok)
+                .map(|record| String::from_utf8_lossy(&record).into_owned()),
+        )
+    } else {
+        Box::new(filehandle.lines().map_while(Result::ok))
+    }
+}
+
+// Unit tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Feeds every record of the text to a new order checker, like do_run does while advancing.
+    fn check_order(text: &str, mode: OrderCheckMode) -> (anyhow::Result<()>, bool) {
+        let mut order_checker = OrderChecker::new(1, mode, |line1, line2| line1.cmp(line2));
+
+        let mut records = read_records(Box::new(Cursor::new(text.to_string())), false);
+        let result = records.try_for_each(|line| order_checker.check(&line));
+
+        (result, order_checker.is_disordered)
+    }
+
+    // Collects the columns of the merged lines as owned values.
+    fn merge(lines1: &[&str], lines2: &[&str]) -> Vec<(u8, String)> {
+        let to_lines = |lines: &[&str]| -> Vec<anyhow::Result<String>> {
+            lines.iter().map(|line| Ok(line.to_string())).collect()
+        };
+
+        let mut columns = vec![];
+        merge_lines(
+            to_lines(lines1).into_iter(),
+            to_lines(lines2).into_iter(),
+            |line1, line2| line1.cmp(line2),
+            |column| {
+                columns.push(match column {
+                    Column::Col1(text) => (1, text.to_string()),
+                    Column::Col2(text) => (2, text.to_string()),
+                    Column::Col3(text) => (3, text.to_string()),
+                });
+                Ok(())
+            },
+        )
+        .unwrap();
+        columns
+    }
+
+    #[test]
+    fn test_merge_lines() {
+        let column = |number, text: &str| (number, text.to_string());
+
+        // The equal, less, and greater branches, then the rest of the first sequence.
+        assert_eq!(
+            merge(&["a", "b", "d", "e"], &["b", "c"]),
+            [
+                column(1, "a"),
+                column(3, "b"),
+                column(2, "c"),
+                column(1, "d"),
+                column(1, "e"),
+            ]
+        );
+
+        // The rest of the second sequence.
+        assert_eq!(
+            merge(&["a"], &["a", "b", "c"]),
+            [column(3, "a"), column(2, "b"), column(2, "c")]
+        );
+
+        // Either sequence can be empty.
+        assert_eq!(merge(&[], &["a"]), [column(2, "a")]);
+        assert_eq!(merge(&["a"], &[]), [column(1, "a")]);
+        assert_eq!(merge(&[], &[]), []);
+
+        // An error stops the merge.
+        let lines1 = vec![Ok(String::from("a")), Err(anyhow::anyhow!("unsorted"))];
+        let mut columns = 0;
+        let result = merge_lines(
+            lines1.into_iter(),
+            std::iter::empty(),
+            |line1, line2| line1.cmp(line2),
+            |_| {
+                columns += 1;
+                Ok(())
+            },
+        );
+        assert_eq!(result.unwrap_err().to_string(), "unsorted");
+        assert_eq!(columns, 1);
+    }
+
+    #[test]
+    fn test_format_column() {
+        // Each combination of the -1, -2, and -3 flags, as the rows of "a" in column 1, "b" in
+        // column 2, and "c" in column 3, like `comm` prints them.
+        let cases = [
+            ("", [Some("a"), Some("\tb"), Some("\t\tc")]),
+            ("-1", [None, Some("b"), Some("\tc")]),
+            ("-2", [Some("a"), None, Some("\tc")]),
+            ("-3", [Some("a"), Some("\tb"), None]),
+            ("-12", [None, None, Some("c")]),
+            ("-13", [None, Some("b"), None]),
+            ("-23", [Some("a"), None, None]),
+            ("-123", [None, None, None]),
+        ];
+
+        for (flags, expected) in cases {
+            let show_columns = [
+                !flags.contains('1'),
+                !flags.contains('2'),
+                !flags.contains('3'),
+            ];
+
+            let rows = [
+                format_column(&Column::Col1("a"), show_columns, "\t"),
+                format_column(&Column::Col2("b"), show_columns, "\t"),
+                format_column(&Column::Col3("c"), show_columns, "\t"),
+            ];
+
+            assert_eq!(rows, expected.map(|row| row.map(String::from)), "{flags}");
+        }
+
+        // The delimiter can be longer than one character.
+        assert_eq!(
+            format_column(&Column::Col3("c"), [true, true, true], "::"),
+            Some(String::from("::::c"))
+        );
+    }
+
+    #[test]
+    fn test_read_records() {
+        let read = |text: &str, zero_terminated: bool| -> Vec<String> {
+            read_records(Box::new(Cursor::new(text.to_string())), zero_terminated).collect()
+        };
+
+        // The last record is kept whether or not it is terminated.
+        assert_eq!(read("a\0b c\nd\0", true), ["a", "b c\nd"]);
+        assert_eq!(read("a\0b c\nd", true), ["a", "b c\nd"]);
+        assert_eq!(read("a\0b\nc", false), ["a\0b", "c"]);
+        assert!(read("", true).is_empty());
+    }
+
+    #[test]
+    fn test_order_checker() {
+        // Sorted input, including repeated lines, passes in every mode.
+        for mode in [
+            OrderCheckMode::Strict,
+            OrderCheckMode::Warn,
+            OrderCheckMode::Skip,
+        ] {
+            let (result, is_disordered) = check_order("a\nb\nb\nc\n", mode);
+            assert!(result.is_ok());
+            assert!(!is_disordered);
+        }
+
+        // --check-order fails on the first line that is less than the previous one.
+        let (result, is_disordered) = check_order("a\nc\nb\n", OrderCheckMode::Strict);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "comm: file 1 is not in sorted order"
+        );
+        assert!(is_disordered);
+
+        // By default, the disorder is only remembered so that do_run can fail at the end.
+        let (result, is_disordered) = check_order("a\nc\nb\n", OrderCheckMode::Warn);
+        assert!(result.is_ok());
+        assert!(is_disordered);
+
+        // --nocheck-order does not look at the order at all.
+        let (result, is_disordered) = check_order("a\nc\nb\n", OrderCheckMode::Skip);
+        assert!(result.is_ok());
+        assert!(!is_disordered);
     }
 }
@@ -10,6 +10,14 @@ const EMPTY: &str = "tests/inputs/empty.txt";
 const FILE1: &str = "tests/inputs/file1.txt";
 const FILE2: &str = "tests/inputs/file2.txt";
 const BLANK: &str = "tests/inputs/blank.txt";
+const UNSORTED: &str = "tests/inputs/unsorted.txt";
+const MIXED_CASE: &str = "tests/inputs/mixed_case.txt";
+const FILE1_NUL: &str = "tests/inputs/file1_nul.txt";
+const FILE2_NUL: &str = "tests/inputs/file2_nul.txt";
+const TRAILING_WS1: &str = "tests/inputs/trailing_ws1.txt";
+const TRAILING_WS2: &str = "tests/inputs/trailing_ws2.txt";
+const NUMBERS1: &str = "tests/inputs/numbers1.txt";
+const NUMBERS2: &str = "tests/inputs/numbers2.txt";
 
 // --------------------------------------------------
 #[test]
@@ -74,6 +82,20 @@ fn dies_both_stdin() -> Result<()> {
     Ok(())
 }
 
+// --------------------------------------------------
+#[test]
+fn dies_both_stdin_exact_message() -> Result<()> {
+    let expected = "Both input files cannot be STDIN (\"-\")\n";
+    Command::cargo_bin(PRG)?
+        .args(["-", "-"])
+        .write_stdin("a\n")
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(expected);
+    Ok(())
+}
+
 // --------------------------------------------------
 fn run(args: &[&str], expected_file: &str) -> Result<()> {
     let expected = fs::read_to_string(expected_file)?;
@@ -265,6 +287,18 @@ fn stdin_file2() -> Result<()> {
     )
 }
 
+// --------------------------------------------------
+#[test]
+fn stdin_file2_columns() -> Result<()> {
+    run_stdin(&["-", FILE2], FILE1, "tests/expected/file1_file2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn file1_stdin_columns() -> Result<()> {
+    run_stdin(&[FILE1, "-"], FILE2, "tests/expected/file1_file2.out")
+}
+
 // --------------------------------------------------
 #[test]
 fn file1_file2_delim() -> Result<()> {
@@ -342,3 +376,257 @@ fn file1_file2_123_delim() -> Result<()> {
 fn blank_file1() -> Result<()> {
     run(&[BLANK, FILE1], "tests/expected/blank_file1.out")
 }
+
+// --------------------------------------------------
+#[test]
+fn file1_file2_total() -> Result<()> {
+    run(
+        &[FILE1, FILE2, "--total"],
+        "tests/expected/file1_file2.total.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn file1_file2_12_total() -> Result<()> {
+    // The suppressed columns are still counted.
+    run(
+        &[FILE1, FILE2, "-12", "--total"],
+        "tests/expected/file1_file2.12.total.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn file1_file2_123_total_delim() -> Result<()> {
+    run(
+        &[FILE1, FILE2, "-123", "-d", ":", "--total"],
+        "tests/expected/file1_file2.123.total.delim.out",
+    )
+}
+
+// --------------------------------------------------
+// order checking
+// --------------------------------------------------
+#[test]
+fn sorted_no_warning() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args([FILE1, FILE2])
+        .assert()
+        .success()
+        .stderr("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn file1_file2_verbose() -> Result<()> {
+    // The summary goes to STDERR, so STDOUT is the same as without it.
+    let expected = fs::read_to_string("tests/expected/file1_file2.out")?;
+    Command::cargo_bin(PRG)?
+        .args(["--verbose", FILE1, FILE2])
+        .assert()
+        .success()
+        .stdout(expected)
+        .stderr(format!(
+            "unique to {FILE1}: 3\n\
+             unique to {FILE2}: 1\n\
+             common to both: 1\n"
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn file1_file2_1_verbose() -> Result<()> {
+    // Suppressed columns are still counted.
+    Command::cargo_bin(PRG)?
+        .args(["-1", "--verbose", FILE1, FILE2])
+        .assert()
+        .success()
+        .stderr(format!(
+            "unique to {FILE1}: 3\n\
+             unique to {FILE2}: 1\n\
+             common to both: 1\n"
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn unsorted_warns() -> Result<()> {
+    let expected = fs::read_to_string("tests/expected/unsorted_file2.out")?;
+    Command::cargo_bin(PRG)?
+        .args([UNSORTED, FILE2])
+        .assert()
+        .failure()
+        .stdout(expected)
+        .stderr(
+            "comm: file 1 is not in sorted order\n\
+             comm: input is not in sorted order\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn unsorted_check_order() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--check-order", FILE2, UNSORTED])
+        .assert()
+        .failure()
+        .stderr("comm: file 2 is not in sorted order\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn unsorted_nocheck_order() -> Result<()> {
+    let expected = fs::read_to_string("tests/expected/unsorted_file2.out")?;
+    Command::cargo_bin(PRG)?
+        .args(["--nocheck-order", UNSORTED, FILE2])
+        .assert()
+        .success()
+        .stdout(expected)
+        .stderr("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn check_order_respects_ignore_case() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--check-order", MIXED_CASE, FILE2])
+        .assert()
+        .failure()
+        .stderr("comm: file 1 is not in sorted order\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["--check-order", "-i", MIXED_CASE, FILE2])
+        .assert()
+        .success()
+        .stderr("");
+    Ok(())
+}
+
+// --------------------------------------------------
+// zero-terminated
+// --------------------------------------------------
+fn run_bytes(args: &[&str], expected_file: &str) -> Result<()> {
+    let expected = fs::read(expected_file)?;
+    let output = Command::cargo_bin(PRG)?.args(args).output().expect("fail");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn file1_file2_z() -> Result<()> {
+    run_bytes(
+        &["-z", FILE1_NUL, FILE2_NUL],
+        "tests/expected/file1_file2.z.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn file1_file2_23_i_z() -> Result<()> {
+    run_bytes(
+        &["--zero-terminated", "-23", "-i", FILE1_NUL, FILE2_NUL],
+        "tests/expected/file1_file2.23.i.z.out",
+    )
+}
+
+// --------------------------------------------------
+// numeric
+// --------------------------------------------------
+#[test]
+fn numbers1_numbers2_numeric() -> Result<()> {
+    run(
+        &["--numeric", NUMBERS1, NUMBERS2],
+        "tests/expected/numbers1_numbers2.n.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn numbers1_numbers2_not_numeric() -> Result<()> {
+    // Compared as strings, "10" sorts before "2", so the input is not in sorted order.
+    Command::cargo_bin(PRG)?
+        .args(["--check-order", NUMBERS1, NUMBERS2])
+        .assert()
+        .failure()
+        .stderr("comm: file 1 is not in sorted order\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+// trailing whitespace
+// --------------------------------------------------
+#[test]
+fn trailing_whitespace() -> Result<()> {
+    // "foo  " and "FOO" only differ in case and trailing whitespace.
+    Command::cargo_bin(PRG)?
+        .args(["-i", TRAILING_WS1, TRAILING_WS2])
+        .assert()
+        .success()
+        .stdout("bar\t\n\tfoo\nfoo  \n\tqux \n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn trailing_whitespace_ignored() -> Result<()> {
+    // The lines are printed as they are, with the line of file 1 in column 3.
+    Command::cargo_bin(PRG)?
+        .args([
+            "-i",
+            "--ignore-trailing-whitespace",
+            TRAILING_WS1,
+            TRAILING_WS2,
+        ])
+        .assert()
+        .success()
+        .stdout("bar\t\n\t\tfoo  \n\tqux \n");
+    Ok(())
+}
+
+// --------------------------------------------------
+// broken pipe
+// --------------------------------------------------
+#[test]
+fn broken_pipe() -> Result<()> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::process::Stdio;
+
+    // Feed many more lines than a pipe buffer holds, and stop reading after the first one, like
+    // `commr - empty.txt | head -n 1`.
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin(PRG))
+        .args(["-", EMPTY])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    let feeder = std::thread::spawn(move || {
+        // commr stops reading when it stops writing, so the input pipe breaks as well.
+        for number in 0..200_000 {
+            if writeln!(stdin, "{number:06}").is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut first_line = String::new();
+    BufReader::new(child.stdout.take().unwrap()).read_line(&mut first_line)?;
+    assert_eq!(first_line, "000000\n");
+
+    // The output pipe is closed once the reader above is dropped.
+    let output = child.wait_with_output()?;
+    feeder.join().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stderr)?, "");
+    Ok(())
+}
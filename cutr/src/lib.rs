@@ -0,0 +1,813 @@
+//! Core logic of cutr: parsing position lists and extracting or printing the selected fields,
+//! bytes, or characters of each line.
+
+use regex::Regex;
+use std::{
+    borrow::Cow,
+    io::{self, BufRead, Read, Write},
+    num::NonZeroUsize,
+    ops::Range,
+};
+
+/// Represents spans of positive integer values.
+pub type PositionList = Vec<Range<usize>>;
+
+/// Represents the variants for extracting fields, bytes or characters.
+#[derive(Debug)]
+pub enum SelectionMode {
+    Fields(PositionList),
+    Bytes(PositionList),
+    Chars(PositionList),
+}
+
+// Squeezing repeated delimiters
+
+/// Wraps a reader so that each run of the delimiter byte is read as a single delimiter.
+pub struct SqueezeDelimiters<R> {
+    inner: R,
+    delimiter_byte: u8,
+    // Remembers whether the last byte handed out was a delimiter, since a run may span reads.
+    previous_was_delimiter: bool,
+}
+
+impl<R: Read> SqueezeDelimiters<R> {
+    /// Creates a reader that squeezes runs of `delimiter_byte` read from `inner`.
+    pub fn new(inner: R, delimiter_byte: u8) -> Self {
+        Self {
+            inner,
+            delimiter_byte,
+            previous_was_delimiter: false,
+        }
+    }
+}
+
+impl<R: Read> Read for SqueezeDelimiters<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let bytes_read = self.inner.read(buf)?;
+
+            // Reached the end of the input.
+            if bytes_read == 0 {
+                return Ok(0);
+            }
+
+            // Compact the buffer in place, dropping any delimiter that follows another delimiter.
+            let mut kept = 0;
+            for i in 0..bytes_read {
+                let byte = buf[i];
+                let is_delimiter = byte == self.delimiter_byte;
+
+                if !(is_delimiter && self.previous_was_delimiter) {
+                    buf[kept] = byte;
+                    kept += 1;
+                }
+
+                self.previous_was_delimiter = is_delimiter;
+            }
+
+            // Returning zero would signal the end of the input, so keep reading when every byte
+            // was dropped.
+            if kept > 0 {
+                return Ok(kept);
+            }
+        }
+    }
+}
+
+// Parsing user-provided position text
+
+/// Parses comma-delimited position entries. The entry can be either single digit or hyphenated
+/// range.
+pub fn parse_position(position_text: String) -> anyhow::Result<PositionList> {
+    position_text
+        .split(',')
+        .map(|value| match parse_single_digit_position(value) {
+            Ok(parsed) => Ok(parsed),
+            Err(_) => match parse_hyphenated_position(value) {
+                Ok(parsed) => Ok(parsed),
+                Err(e) => Err(e),
+            },
+        })
+        .collect()
+}
+
+fn parse_single_digit_position(value: &str) -> anyhow::Result<Range<usize>> {
+    let single_digit_regex = Regex::new(r"^(\d+)$").unwrap();
+
+    match single_digit_regex.captures(value) {
+        Some(captures) => {
+            let n: &str = &captures[0];
+            let n: usize = parse_index(n)?;
+
+            Ok(n..n + 1)
+        }
+        None => anyhow::bail!(r#"illegal list value: "{}""#, value),
+    }
+}
+
+fn parse_hyphenated_position(value: &str) -> anyhow::Result<Range<usize>> {
+    let range_regex = Regex::new(r"^(\d+)-(\d+)$").unwrap();
+
+    match range_regex.captures(value) {
+        Some(captures) => {
+            let n1 = parse_index(&captures[1])?;
+            let n2 = parse_index(&captures[2])?;
+
+            if n1 >= n2 {
+                anyhow::bail!(
+                    "First number in range ({}) must be lower than second number ({})",
+                    n1 + 1,
+                    n2 + 1,
+                );
+            }
+
+            Ok(n1..n2 + 1)
+        }
+        None => anyhow::bail!(r#"illegal list value: "{}""#, value),
+    }
+}
+
+/// Parses a string into a positive index value one less than the given number.
+///
+/// The given string may not start with a plus sign, and the parsed value must be greater than
+/// zero.
+fn parse_index(index_text: &str) -> anyhow::Result<usize> {
+    // Create a closure that formats an error string.
+    let error_message =
+        || -> anyhow::Error { anyhow::anyhow!(r#"illegal list value: "{}""#, index_text) };
+
+    // Check if the input value starts with a plus sign.
+    if index_text.starts_with('+') {
+        // Return early with an error.
+        anyhow::bail!(error_message());
+    }
+
+    // Parse the input text, indicating the return type of std::num::NonZeroUsize (aka positive
+    // integer).
+    match index_text.parse::<NonZeroUsize>() {
+        Ok(value) => {
+            // Cast the value from NonZeroUsize to a usize.
+            let value: NonZeroUsize = value;
+            let value: usize = usize::from(value);
+
+            // Decrement the value to a zero based offset.
+            Ok(value - 1)
+        }
+        Err(_) => Err(error_message()),
+    }
+}
+
+// Extracting selected part from a line
+
+pub fn extract_fields_from_record(
+    record: &csv::StringRecord,
+    position_list: &[Range<usize>],
+) -> Vec<String> {
+    // There is another way to write this function so that it will return a Vec<&str>, which will be
+    // slightly more memory efficient as it won't make copies of strings. The trade off is that we
+    // must indicate the lifetimes.
+    position_list
+        .iter()
+        .cloned()
+        .flat_map(|range| range.filter_map(|i| record.get(i)))
+        .map(String::from)
+        .collect()
+}
+
+/// Extracts the selected fields from a line whose fields are separated by runs of whitespace, as
+/// in the output of `ps` or `ls -l`. Leading and trailing whitespace do not create empty fields.
+pub fn extract_whitespace_fields_from_line<'a>(
+    line: &'a str,
+    position_list: &[Range<usize>],
+) -> Vec<&'a str> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+
+    position_list
+        .iter()
+        .cloned()
+        // Select the fields for each range in the position list.
+        .flat_map(|range| range.filter_map(|i| fields.get(i)).copied())
+        .collect()
+}
+
+/// Splits a line on the delimiter. When `squeeze` is true, the empty fields between consecutive
+/// delimiters are dropped as if each run of delimiters were a single one.
+fn split_fields<'a>(line: &'a str, delimiter: &str, squeeze: bool) -> Vec<&'a str> {
+    let fields: Vec<&str> = line.split(delimiter).collect();
+    if !squeeze {
+        return fields;
+    }
+
+    // A leading or trailing run of delimiters still leaves one empty field at that end.
+    let last_index = fields.len() - 1;
+    fields
+        .into_iter()
+        .enumerate()
+        .filter(|(i, field)| !field.is_empty() || *i == 0 || *i == last_index)
+        .map(|(_, field)| field)
+        .collect()
+}
+
+/// Extracts the selected fields from a line split on a delimiter that the csv reader cannot
+/// handle, such as a multibyte UTF-8 character. When `squeeze` is true, the empty fields between
+/// consecutive delimiters are dropped as if each run of delimiters were a single one.
+pub fn extract_split_fields_from_line<'a>(
+    line: &'a str,
+    position_list: &[Range<usize>],
+    delimiter: &str,
+    squeeze: bool,
+) -> Vec<&'a str> {
+    let fields = split_fields(line, delimiter, squeeze);
+
+    position_list
+        .iter()
+        .cloned()
+        // Select the fields for each range in the position list.
+        .flat_map(|range| range.filter_map(|i| fields.get(i)).copied())
+        .collect()
+}
+
+pub fn extract_bytes_from_line(line: &str, position_list: &[Range<usize>]) -> String {
+    let bytes: &[u8] = line.as_bytes();
+
+    // We use std::iter::Copied to create copies of the elements. The reason is that Iterator::get
+    // returns a vector of byte references (&Vec<&u8>), but String::from_utf8_lossy expects a slice
+    // of bytes (&[u8]).
+    let selected: Vec<u8> = position_list
+        .iter()
+        .cloned()
+        // Select the bytes for each range in the position list.
+        .flat_map(|range| range.filter_map(|i| bytes.get(i)).copied())
+        .collect();
+
+    // Create a possibly invalid UTF-8 string from bytes.
+    let selected: Cow<str> = String::from_utf8_lossy(&selected);
+
+    // Clone the data as needed.
+    let selected: String = selected.into_owned();
+
+    selected
+}
+
+pub fn extract_chars_from_line(line: &str, position_list: &[Range<usize>]) -> String {
+    let chars: Vec<char> = line.chars().collect();
+
+    position_list
+        .iter()
+        .cloned()
+        // Select the characters for each range in the position list.
+        .flat_map(|range| range.filter_map(|i| chars.get(i)))
+        .collect()
+}
+
+// Printing selected part of the file
+
+/// Warns about the records whose number of fields differs from the first record, which usually
+/// means that a line uses another delimiter.
+pub struct FieldCountChecker<'a> {
+    filename: &'a str,
+    warning_writer: &'a mut dyn Write,
+    first_field_count: Option<usize>,
+}
+
+impl<'a> FieldCountChecker<'a> {
+    /// Creates a checker for the named file that writes its warnings to `warning_writer`.
+    pub fn new(filename: &'a str, warning_writer: &'a mut dyn Write) -> Self {
+        FieldCountChecker {
+            filename,
+            warning_writer,
+            first_field_count: None,
+        }
+    }
+
+    fn check(&mut self, field_count: usize, line_number: u64) -> anyhow::Result<()> {
+        match self.first_field_count {
+            None => self.first_field_count = Some(field_count),
+            Some(first_field_count) if first_field_count != field_count => writeln!(
+                self.warning_writer,
+                "{}: line {line_number}: found {field_count} fields, but the first line has {first_field_count}",
+                self.filename
+            )?,
+            Some(_) => (),
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the selected fields of each record, parsed with the csv reader using the given
+/// single-byte delimiter. Every record must have as many fields as the first one, unless a
+/// `field_count_checker` is given to warn about the others instead.
+pub fn print_selected_fields(
+    filehandle: impl Read,
+    position_list: &[Range<usize>],
+    delimiter_byte: u8,
+    mut field_count_checker: Option<FieldCountChecker>,
+    writer: impl Write,
+) -> anyhow::Result<()> {
+    // Only accept records with another number of fields when they are reported.
+    let is_flexible = field_count_checker.is_some();
+
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter_byte)
+        .has_headers(false)
+        .flexible(is_flexible)
+        .from_reader(filehandle);
+
+    let mut csv_writer = csv::WriterBuilder::new()
+        .delimiter(delimiter_byte)
+        .flexible(is_flexible)
+        .from_writer(writer);
+
+    for record in csv_reader.records() {
+        let record: csv::StringRecord = record?;
+
+        if let Some(field_count_checker) = &mut field_count_checker {
+            let line_number = record.position().map_or(0, |position| position.line());
+            field_count_checker.check(record.len(), line_number)?;
+        }
+
+        // Unwrap an I/O error from the csv error, so that a closed pipe can be told apart. Any other
+        // csv error is kept as it is.
+        csv_writer
+            .write_record(extract_fields_from_record(&record, position_list))
+            .map_err(|e| {
+                if !e.is_io_error() {
+                    return anyhow::Error::from(e);
+                }
+                match e.into_kind() {
+                    csv::ErrorKind::Io(e) => anyhow::Error::from(e),
+                    _ => unreachable!("is_io_error checked the kind"),
+                }
+            })?;
+    }
+
+    // The csv writer buffers its output, so flush it to surface any write error.
+    csv_writer.flush()?;
+
+    Ok(())
+}
+
+/// Writes the selected fields of each line, treating runs of whitespace as the delimiter.
+pub fn print_selected_whitespace_fields(
+    filehandle: impl BufRead,
+    position_list: &[Range<usize>],
+    mut writer: impl Write,
+) -> anyhow::Result<()> {
+    for line in filehandle.lines() {
+        let line: &str = &line?;
+        // Join the selected fields with a tab since there is no single input delimiter to reuse.
+        writeln!(
+            writer,
+            "{}",
+            extract_whitespace_fields_from_line(line, position_list).join("\t")
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Writes the selected fields of each line split on the given delimiter string, joined by the
+/// same delimiter.
+pub fn print_selected_split_fields(
+    filehandle: impl BufRead,
+    position_list: &[Range<usize>],
+    delimiter: &str,
+    squeeze: bool,
+    mut field_count_checker: Option<FieldCountChecker>,
+    mut writer: impl Write,
+) -> anyhow::Result<()> {
+    for (line_number, line) in (1..).zip(filehandle.lines()) {
+        let line: &str = &line?;
+
+        if let Some(field_count_checker) = &mut field_count_checker {
+            // Count the same fields that the selection is made from.
+            let field_count = split_fields(line, delimiter, squeeze).len();
+            field_count_checker.check(field_count, line_number)?;
+        }
+
+        writeln!(
+            writer,
+            "{}",
+            extract_split_fields_from_line(line, position_list, delimiter, squeeze).join(delimiter)
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Writes the selected bytes of each line.
+pub fn print_selected_bytes(
+    filehandle: impl BufRead,
+    position_list: &[Range<usize>],
+    mut writer: impl Write,
+) -> anyhow::Result<()> {
+    for line in filehandle.lines() {
+        let line: &str = &line?;
+        writeln!(writer, "{}", extract_bytes_from_line(line, position_list))?;
+    }
+
+    Ok(())
+}
+
+/// Writes the selected characters of each line.
+pub fn print_selected_chars(
+    filehandle: impl BufRead,
+    position_list: &[Range<usize>],
+    mut writer: impl Write,
+) -> anyhow::Result<()> {
+    for line in filehandle.lines() {
+        let line: &str = &line?;
+        writeln!(writer, "{}", extract_chars_from_line(line, position_list))?;
+    }
+
+    Ok(())
+}
+
+// Unit testing
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod unit_tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_position() {
+        // The empty string is an error.
+        assert!(parse_position("".to_string()).is_err());
+
+        // Zero is an error.
+        let result = parse_position("0".to_string());
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            r#"illegal list value: "0""#
+        );
+
+        let result = parse_position("0-1".to_string());
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            r#"illegal list value: "0""#
+        );
+
+        // A leading "+" is an error.
+        let result = parse_position("+1".to_string());
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            r#"illegal list value: "+1""#
+        );
+
+        let result = parse_position("+1-2".to_string());
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            r#"illegal list value: "+1-2""#
+        );
+
+        let result = parse_position("1-+2".to_string());
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            r#"illegal list value: "1-+2""#
+        );
+
+        // Any non-number is an error.
+        let result = parse_position("a".to_string());
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            r#"illegal list value: "a""#
+        );
+
+        let result = parse_position("1,a".to_string());
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            r#"illegal list value: "a""#
+        );
+
+        let result = parse_position("1-a".to_string());
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            r#"illegal list value: "1-a""#
+        );
+
+        let result = parse_position("a-1".to_string());
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            r#"illegal list value: "a-1""#
+        );
+
+        // Improper ranges
+        assert!(parse_position("-".to_string()).is_err());
+        assert!(parse_position(",".to_string()).is_err());
+        assert!(parse_position("1,".to_string()).is_err());
+        assert!(parse_position("1-".to_string()).is_err());
+        assert!(parse_position("1-1-1".to_string()).is_err());
+        assert!(parse_position("1-1-a".to_string()).is_err());
+
+        // First number must be less than the second
+        let result = parse_position("1-1".to_string());
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            r#"First number in range (1) must be lower than second number (1)"#
+        );
+
+        let result = parse_position("2-1".to_string());
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            r#"First number in range (2) must be lower than second number (1)"#
+        );
+
+        // Accepable ranges
+        let result = parse_position("1".to_string());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), vec![0..1]);
+
+        let result = parse_position("1".to_string());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), vec![0..1]);
+
+        let result = parse_position("01".to_string());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), vec![0..1]);
+
+        let result = parse_position("1,3".to_string());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), vec![0..1, 2..3]);
+
+        let result = parse_position("001,003".to_string());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), vec![0..1, 2..3]);
+
+        let result = parse_position("1-3".to_string());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), vec![0..3]);
+
+        let result = parse_position("0001-03".to_string());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), vec![0..3]);
+
+        let result = parse_position("1,7,3-5".to_string());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), vec![0..1, 6..7, 2..5]);
+
+        let result = parse_position("15,19-20".to_string());
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), vec![14..15, 18..20]);
+    }
+
+    #[test]
+    fn test_extract_fields() {
+        let rec = csv::StringRecord::from(vec!["Captain", "Sham", "12345"]);
+        assert_eq!(extract_fields_from_record(&rec, &[0..1]), &["Captain"]);
+        assert_eq!(extract_fields_from_record(&rec, &[1..2]), &["Sham"]);
+        assert_eq!(
+            extract_fields_from_record(&rec, &[0..1, 2..3]),
+            &["Captain", "12345"]
+        );
+        assert_eq!(
+            extract_fields_from_record(&rec, &[0..1, 3..4]),
+            &["Captain"]
+        );
+        assert_eq!(
+            extract_fields_from_record(&rec, &[1..2, 0..1]),
+            &["Sham", "Captain"]
+        );
+    }
+
+    #[test]
+    fn test_extract_whitespace_fields() {
+        let line = "  a   b  c";
+        assert_eq!(extract_whitespace_fields_from_line(line, &[0..1]), &["a"]);
+        assert_eq!(extract_whitespace_fields_from_line(line, &[1..2]), &["b"]);
+        assert_eq!(
+            extract_whitespace_fields_from_line(line, &[0..1, 2..3]),
+            &["a", "c"]
+        );
+        assert_eq!(
+            extract_whitespace_fields_from_line(line, &[0..3]),
+            &["a", "b", "c"]
+        );
+        assert_eq!(
+            extract_whitespace_fields_from_line(line, &[2..3, 3..4]),
+            &["c"]
+        );
+        assert!(extract_whitespace_fields_from_line("", &[0..1]).is_empty());
+    }
+
+    #[test]
+    fn test_squeeze_delimiters() {
+        let squeeze = |text: &str, delimiter_byte: u8| -> String {
+            let mut squeezed = String::new();
+            SqueezeDelimiters::new(text.as_bytes(), delimiter_byte)
+                .read_to_string(&mut squeezed)
+                .unwrap();
+            squeezed
+        };
+
+        assert_eq!(squeeze("a,,b,,,c\n", b','), "a,b,c\n");
+        assert_eq!(squeeze("a  b\nc   d\n", b' '), "a b\nc d\n");
+        assert_eq!(squeeze("a,b\n", b','), "a,b\n");
+        assert_eq!(squeeze("", b','), "");
+
+        // A run of delimiters that spans two reads is still collapsed.
+        let mut squeezed = String::new();
+        SqueezeDelimiters::new("a,,".as_bytes().chain(",,b".as_bytes()), b',')
+            .read_to_string(&mut squeezed)
+            .unwrap();
+        assert_eq!(squeezed, "a,b");
+
+        // The doubled delimiters no longer produce empty fields.
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .delimiter(b' ')
+            .has_headers(false)
+            .from_reader(SqueezeDelimiters::new("x  y   z\n".as_bytes(), b' '));
+        let record = csv_reader.records().next().unwrap().unwrap();
+        assert_eq!(extract_fields_from_record(&record, &[1..3]), &["y", "z"]);
+    }
+
+    #[test]
+    fn test_extract_split_fields() {
+        let line = "a│b│c";
+        assert_eq!(
+            extract_split_fields_from_line(line, &[0..1], "│", false),
+            &["a"]
+        );
+        assert_eq!(
+            extract_split_fields_from_line(line, &[0..1, 2..3], "│", false),
+            &["a", "c"]
+        );
+        assert_eq!(
+            extract_split_fields_from_line(line, &[2..3, 1..2], "│", false),
+            &["c", "b"]
+        );
+        assert_eq!(
+            extract_split_fields_from_line(line, &[2..4], "│", false),
+            &["c"]
+        );
+        assert_eq!(
+            extract_split_fields_from_line("甲，乙", &[1..2], "，", false),
+            &["乙"]
+        );
+
+        // Consecutive delimiters produce empty fields unless squeezed.
+        let line = "a││b│";
+        assert_eq!(
+            extract_split_fields_from_line(line, &[0..4], "│", false),
+            &["a", "", "b", ""]
+        );
+        assert_eq!(
+            extract_split_fields_from_line(line, &[0..4], "│", true),
+            &["a", "b", ""]
+        );
+        assert_eq!(
+            extract_split_fields_from_line("││a", &[0..2], "│", true),
+            &["", "a"]
+        );
+    }
+
+    #[test]
+    fn test_extract_chars() {
+        assert_eq!(extract_chars_from_line("", &[0..1]), "".to_string());
+        assert_eq!(extract_chars_from_line("ábc", &[0..1]), "á".to_string());
+        assert_eq!(
+            extract_chars_from_line("ábc", &[0..1, 2..3]),
+            "ác".to_string()
+        );
+        assert_eq!(extract_chars_from_line("ábc", &[0..3]), "ábc".to_string());
+        assert_eq!(
+            extract_chars_from_line("ábc", &[2..3, 1..2]),
+            "cb".to_string()
+        );
+        assert_eq!(
+            extract_chars_from_line("ábc", &[0..1, 1..2, 4..5]),
+            "áb".to_string()
+        );
+    }
+
+    #[test]
+    fn test_extract_bytes() {
+        assert_eq!(extract_bytes_from_line("ábc", &[0..1]), "�".to_string());
+        assert_eq!(extract_bytes_from_line("ábc", &[0..2]), "á".to_string());
+        assert_eq!(extract_bytes_from_line("ábc", &[0..3]), "áb".to_string());
+        assert_eq!(extract_bytes_from_line("ábc", &[0..4]), "ábc".to_string());
+        assert_eq!(
+            extract_bytes_from_line("ábc", &[3..4, 2..3]),
+            "cb".to_string()
+        );
+        assert_eq!(
+            extract_bytes_from_line("ábc", &[0..2, 5..6]),
+            "á".to_string()
+        );
+    }
+
+    #[test]
+    fn test_print_selected() {
+        let text = "Captain,Sham,12345\nábc,def,ghi\n";
+
+        let mut output = vec![];
+        print_selected_fields(text.as_bytes(), &[0..1, 2..3], b',', None, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Captain,12345\nábc,ghi\n"
+        );
+
+        let mut output = vec![];
+        print_selected_bytes(text.as_bytes(), &[0..2], &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "Ca\ná\n");
+
+        let mut output = vec![];
+        print_selected_chars(text.as_bytes(), &[0..2], &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "Ca\náb\n");
+
+        let mut output = vec![];
+        print_selected_split_fields(
+            "a│b│c\n".as_bytes(),
+            &[0..1, 2..3],
+            "│",
+            false,
+            None,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "a│c\n");
+
+        let mut output = vec![];
+        print_selected_whitespace_fields("  a   b  c\n".as_bytes(), &[1..3], &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "b\tc\n");
+    }
+
+    #[test]
+    fn test_strict_delimiter() {
+        // The third line uses a tab instead of a comma.
+        let text = "name,age\nAlice,30\nBob\t25\nCarol,41\n";
+
+        let mut output = vec![];
+        let mut warnings = vec![];
+        print_selected_fields(
+            text.as_bytes(),
+            &[0..1],
+            b',',
+            Some(FieldCountChecker::new("people.csv", &mut warnings)),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "name\nAlice\nBob\t25\nCarol\n"
+        );
+        assert_eq!(
+            String::from_utf8(warnings).unwrap(),
+            "people.csv: line 3: found 1 fields, but the first line has 2\n"
+        );
+
+        // Without the strict mode, the inconsistent record is an error.
+        let result = print_selected_fields(text.as_bytes(), &[0..1], b',', None, io::sink());
+        assert!(result.is_err());
+
+        // The multibyte delimiter is checked the same way.
+        let mut warnings = vec![];
+        print_selected_split_fields(
+            "a│b\nc│d\ne,f\n".as_bytes(),
+            &[0..1],
+            "│",
+            false,
+            Some(FieldCountChecker::new("-", &mut warnings)),
+            io::sink(),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(warnings).unwrap(),
+            "-: line 3: found 1 fields, but the first line has 2\n"
+        );
+
+        // With squeezed delimiters, a leading delimiter still leaves an empty first field, which
+        // is counted like it is selected.
+        let mut output = vec![];
+        let mut warnings = vec![];
+        print_selected_split_fields(
+            "a│b\n│c││d\nef\n".as_bytes(),
+            &[1..2],
+            "│",
+            true,
+            Some(FieldCountChecker::new("-", &mut warnings)),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "b\nc\n\n");
+        assert_eq!(
+            String::from_utf8(warnings).unwrap(),
+            "-: line 2: found 3 fields, but the first line has 2\n\
+             -: line 3: found 1 fields, but the first line has 2\n"
+        );
+    }
+}
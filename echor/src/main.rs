@@ -1,34 +1,27 @@
-use clap::Arg;
-use clap::ArgAction;
-use clap::Command;
+use clap::Parser;
+use cli_common::exit_with_error;
+use std::io::{self, Write};
 
-fn main() {
-    let matches = Command::new("echor")
-        .version("0.1.0")
-        .author("Masatoshi N")
-        .about("Rust version of `echo`")
-        .arg(
-            Arg::new("text")
-                .value_name("TEXT")
-                .help("Input text")
-                .required(true)
-                .num_args(1..),
-        )
-        .arg(
-            Arg::new("omit_newline")
-                .short('n')
-                .action(ArgAction::SetTrue)
-                .help("Do not print newline"),
-        )
-        .get_matches();
+/// Rust version of `echo`
+#[derive(Debug, Parser)]
+#[command(author = "Masatoshi N", version, about)]
+struct Args {
+    /// Input text
+    #[arg(value_name = "TEXT", required = true)]
+    text: Vec<String>,
 
-    // The type annotation is required because Iterator::collect can return many diffrent types.
-    let text: Vec<String> = matches.get_many("text").unwrap().cloned().collect();
+    /// Do not print newline
+    #[arg(short = 'n')]
+    omit_newline: bool,
+}
 
-    let omit_newline = matches.get_flag("omit_newline");
-    let ending = if omit_newline { "" } else { "\n" };
+fn main() {
+    let args = Args::parse();
 
-    print!("{}{}", text.join(" "), ending);
+    let ending = if args.omit_newline { "" } else { "\n" };
 
-    ()
+    // Unlike print!, write! returns an error on a closed pipe instead of panicking.
+    if let Err(e) = write!(io::stdout(), "{}{}", args.text.join(" "), ending) {
+        exit_with_error(e.into(), 1);
+    }
 }
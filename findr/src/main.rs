@@ -1,4 +1,13 @@
-use clap::Parser;
+use clap::{CommandFactory, Parser};
+use cli_common::ColorChoice;
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, SystemTime},
+};
 use walkdir::WalkDir;
 
 /// Search for files in a directory hierarchy.
@@ -20,6 +29,37 @@ struct Args {
     )]
     names: Vec<regex::Regex>,
 
+    /// Name(s), matched case-insensitively
+    #[arg(
+        long = "iname",
+        value_name = "NAME",
+        value_parser = parse_iname,
+        action = clap::ArgAction::Append,
+        num_args = 0..,
+    )]
+    inames: Vec<regex::Regex>,
+
+    /// Name glob(s), like find's -name: *, ? and [...]
+    #[arg(
+        short = 'g',
+        long = "glob",
+        value_name = "GLOB",
+        value_parser = parse_glob,
+        action = clap::ArgAction::Append,
+        num_args = 0..,
+    )]
+    globs: Vec<globset::GlobMatcher>,
+
+    /// Name glob(s), matched case-insensitively
+    #[arg(
+        long = "iglob",
+        value_name = "GLOB",
+        value_parser = parse_iglob,
+        action = clap::ArgAction::Append,
+        num_args = 0..,
+    )]
+    iglobs: Vec<globset::GlobMatcher>,
+
     /// Entry type(s)
     #[arg(
         short = 't',
@@ -30,6 +70,331 @@ struct Args {
         num_args = 0..,
     )]
     entry_types: Vec<EntryType>,
+
+    /// Path(s), matched against the whole path rather than the name
+    #[arg(
+        long = "path",
+        value_name = "PATH",
+        value_parser = regex::Regex::new,
+        action = clap::ArgAction::Append,
+        num_args = 0..,
+    )]
+    path_regexes: Vec<regex::Regex>,
+
+    /// Exclude the entries whose names match any of the NAME(s)
+    #[arg(
+        long = "not-name",
+        value_name = "NAME",
+        value_parser = regex::Regex::new,
+        action = clap::ArgAction::Append,
+        num_args = 0..,
+    )]
+    not_names: Vec<regex::Regex>,
+
+    /// Exclude the entries of any of the TYPE(s)
+    #[arg(
+        long = "not-type",
+        value_name = "TYPE",
+        value_parser = clap::value_parser!(EntryType),
+        action = clap::ArgAction::Append,
+        num_args = 0..,
+    )]
+    not_entry_types: Vec<EntryType>,
+
+    /// File size(s), like find's -size: [+|-]N[c|k|M|G|b], in 512-byte blocks without a suffix;
+    /// only regular files match
+    #[arg(
+        long = "size",
+        value_name = "SIZE",
+        value_parser = parse_size,
+        action = clap::ArgAction::Append,
+        allow_hyphen_values = true,
+    )]
+    sizes: Vec<SizeFilter>,
+
+    /// Modification time(s) in days, like find's -mtime: [+|-]N
+    #[arg(
+        long = "mtime",
+        value_name = "DAYS",
+        value_parser = parse_mtime,
+        action = clap::ArgAction::Append,
+        allow_hyphen_values = true,
+    )]
+    mtimes: Vec<MtimeFilter>,
+
+    /// Match only the entries modified more recently than FILE
+    #[arg(long, value_name = "FILE")]
+    newer: Option<PathBuf>,
+
+    /// Match only empty files and directories
+    #[arg(long)]
+    empty: bool,
+
+    /// Sort the paths of each search path by KEY instead of printing them in traversal order
+    #[arg(
+        long,
+        value_name = "KEY",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "name"
+    )]
+    sort: Option<SortKey>,
+
+    /// Follow symbolic links, reporting the type of their targets (so `-t l` matches nothing); a
+    /// link back to one of its own ancestors is reported as a loop instead of being descended
+    /// into again
+    #[arg(short = 'L', long)]
+    follow: bool,
+
+    /// Descend at most DEPTH levels below the search paths (0 is the search path itself)
+    #[arg(long, value_name = "DEPTH")]
+    max_depth: Option<usize>,
+
+    /// Skip the entries less than DEPTH levels below the search paths
+    #[arg(long, value_name = "DEPTH", default_value_t = 0)]
+    min_depth: usize,
+
+    /// Print paths relative to DIR; paths outside DIR are printed unchanged
+    #[arg(long, value_name = "DIR")]
+    relative_to: Option<PathBuf>,
+
+    /// Append " -> TARGET" to the paths of symbolic links
+    #[arg(long)]
+    show_target: bool,
+
+    /// When to color the paths of directories and symbolic links, like ls; a bare --color means
+    /// always
+    #[arg(
+        long,
+        value_name = "WHEN",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "always",
+        default_value_t = ColorChoice::Never
+    )]
+    color: ColorChoice,
+
+    // NOTE: The flatten command will merge the ActionArguments in the Args struct.
+    #[command(flatten)]
+    action_arguments: ActionArguments,
+}
+
+// Only one action can be taken on the matching entries. Printing is the default.
+#[derive(Debug, clap::Args, Clone)]
+#[group(required = false, multiple = false)]
+struct ActionArguments {
+    /// Print the matching paths, one per line (default)
+    #[arg(long)]
+    print: bool,
+
+    /// Print the matching paths, each followed by a NUL character
+    #[arg(long)]
+    print0: bool,
+
+    /// Print only the number of matching paths
+    #[arg(long)]
+    count: bool,
+
+    /// Run COMMAND for each matching path, replacing {} with the path; end COMMAND with ';', and
+    /// end it with '{} +' to run it once with all the paths instead
+    #[arg(
+        long,
+        value_name = "COMMAND",
+        num_args = 1..,
+        allow_hyphen_values = true,
+        value_terminator = ";"
+    )]
+    exec: Option<Vec<String>>,
+}
+
+// Represents what to do with the matching entries
+#[derive(Debug, Clone, PartialEq)]
+enum Action {
+    Print,
+    Print0,
+    Count,
+    Exec(ExecCommand),
+}
+
+// The most bytes of arguments a batched command is given at once. Like xargs, this stays well
+// under the system limit, which also has to hold the environment.
+const MAX_BATCH_BYTES: usize = 128 * 1024;
+
+// Represents an --exec command. Like find, a command ending with "{} +" runs once with all the
+// paths appended, and any other command runs once per path, with every "{}" replaced by the path.
+#[derive(Debug, Clone, PartialEq)]
+struct ExecCommand {
+    args: Vec<String>,
+    is_batch: bool,
+}
+
+impl ExecCommand {
+    fn new(args: &[String]) -> Self {
+        match args {
+            [command @ .., placeholder, terminator]
+                if !command.is_empty() && placeholder == "{}" && terminator == "+" =>
+            {
+                ExecCommand {
+                    args: command.to_vec(),
+                    is_batch: true,
+                }
+            }
+            _ => ExecCommand {
+                args: args.to_vec(),
+                is_batch: false,
+            },
+        }
+    }
+
+    // Builds the command line for the paths. A path replaces a whole "{}" argument as it is, so
+    // that a path that is not valid UTF-8 is passed on unchanged.
+    fn command_line(&self, paths: &[PathBuf]) -> Vec<OsString> {
+        if self.is_batch {
+            return self
+                .args
+                .iter()
+                .map(OsString::from)
+                .chain(paths.iter().map(|path| path.as_os_str().to_owned()))
+                .collect();
+        }
+
+        // Otherwise, the command is run for one path at a time.
+        let path = &paths[0];
+        self.args
+            .iter()
+            .map(|arg| {
+                if arg == "{}" {
+                    path.as_os_str().to_owned()
+                } else {
+                    OsString::from(arg.replace("{}", &path.to_string_lossy()))
+                }
+            })
+            .collect()
+    }
+
+    // Splits the paths of a batched command into chunks whose command lines, counting a pointer
+    // and a terminating NUL for every argument, fit in max_bytes. A chunk always has at least one
+    // path, even if that path alone is too long.
+    fn split_batch<'a>(&self, paths: &'a [PathBuf], max_bytes: usize) -> Vec<&'a [PathBuf]> {
+        let arg_size = |arg: &std::ffi::OsStr| arg.len() + 1 + std::mem::size_of::<usize>();
+        let command_size: usize = self.args.iter().map(|arg| arg_size(arg.as_ref())).sum();
+
+        let mut chunks = vec![];
+        let mut chunk_start = 0;
+        let mut chunk_size = command_size;
+        for (i, path) in paths.iter().enumerate() {
+            let path_size = arg_size(path.as_os_str());
+            if i > chunk_start && chunk_size + path_size > max_bytes {
+                chunks.push(&paths[chunk_start..i]);
+                chunk_start = i;
+                chunk_size = command_size;
+            }
+            chunk_size += path_size;
+        }
+        if chunk_start < paths.len() {
+            chunks.push(&paths[chunk_start..]);
+        }
+        chunks
+    }
+
+    // Runs the command for the paths, and returns whether it succeeded. A command that cannot be
+    // started is reported as a failure.
+    fn run(&self, paths: &[PathBuf]) -> bool {
+        let command_line = self.command_line(paths);
+
+        match Command::new(&command_line[0])
+            .args(&command_line[1..])
+            .status()
+        {
+            Ok(status) => status.success(),
+            Err(e) => {
+                eprintln!("{}: {e}", self.args[0]);
+                false
+            }
+        }
+    }
+}
+
+// Clap ends the --exec values only at ";", so a ";" is inserted after a "{} +" that ends the
+// command, and the options after it are parsed as options again. Like find, an --exec without
+// either terminator, or without a command before it, is an error.
+fn terminate_exec(argv: impl IntoIterator<Item = OsString>) -> Result<Vec<OsString>, String> {
+    let mut terminated_argv = vec![];
+    let mut is_in_exec = false;
+    let mut previous_arg = OsString::new();
+    // The number of arguments of the current --exec so far
+    let mut exec_arg_count = 0;
+
+    for arg in argv {
+        let ends_batch = is_in_exec && arg == "+" && previous_arg == "{}";
+        if !is_in_exec {
+            is_in_exec = arg == "--exec";
+            exec_arg_count = 0;
+        } else if arg == ";" || ends_batch {
+            // The "{}" of "{} +" is not a command either.
+            if exec_arg_count == usize::from(ends_batch) {
+                return Err("--exec: missing COMMAND before the terminator".to_string());
+            }
+            is_in_exec = false;
+        } else {
+            exec_arg_count += 1;
+        }
+
+        previous_arg = arg.clone();
+        terminated_argv.push(arg);
+        if ends_batch {
+            terminated_argv.push(OsString::from(";"));
+        }
+    }
+
+    if is_in_exec {
+        return Err("--exec: missing terminator, end COMMAND with ';' or '{} +'".to_string());
+    }
+    Ok(terminated_argv)
+}
+
+// Represents a --size predicate. Like find, the size of an entry is rounded up to whole units
+// before it is compared, so "-1M" only matches empty files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SizeFilter {
+    ordering: std::cmp::Ordering,
+    unit_count: u64,
+    unit_size: u64,
+}
+
+impl SizeFilter {
+    fn is_match(&self, byte_count: u64) -> bool {
+        byte_count.div_ceil(self.unit_size).cmp(&self.unit_count) == self.ordering
+    }
+}
+
+// Represents a --mtime predicate. Like find, the age of an entry is counted in whole days, so "0"
+// matches the entries modified within the last 24 hours.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MtimeFilter {
+    ordering: std::cmp::Ordering,
+    days: u64,
+}
+
+impl MtimeFilter {
+    fn is_match(&self, age: Duration) -> bool {
+        (age.as_secs() / SECONDS_PER_DAY).cmp(&self.days) == self.ordering
+    }
+}
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+// Represents the order of the paths with --sort
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum SortKey {
+    /// By path, one component at a time, so a directory's contents stay together
+    Name,
+    /// By size, smallest first
+    Size,
+    /// By modification time, oldest first
+    Mtime,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -55,37 +420,265 @@ impl clap::ValueEnum for EntryType {
     }
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
+// Represents all the conditions that an entry must meet to be acted on. The conditions that need
+// no metadata are checked first.
+#[derive(Debug, Clone)]
+struct Filters {
+    entry_types: Vec<EntryType>,
+    not_entry_types: Vec<EntryType>,
+    name_regexes: Vec<regex::Regex>,
+    name_globs: Vec<globset::GlobMatcher>,
+    not_names: Vec<regex::Regex>,
+    path_regexes: Vec<regex::Regex>,
+    sizes: Vec<SizeFilter>,
+    mtimes: Vec<MtimeFilter>,
+    newer_than: Option<SystemTime>,
+    empty: bool,
+    // The time the search started, which the modification times are compared with
+    now: SystemTime,
+}
+
+impl Filters {
+    // Fails when the --newer reference file does not exist.
+    fn new(args: &Args) -> anyhow::Result<Self> {
+        let newer_than = match &args.newer {
+            Some(reference) => Some(
+                fs::metadata(reference)
+                    .and_then(|metadata| metadata.modified())
+                    .map_err(|e| anyhow::anyhow!("{}: {e}", reference.display()))?,
+            ),
+            None => None,
+        };
 
-    if let Err(e) = do_run(args) {
-        eprintln!("{e}");
-        std::process::exit(1);
+        // The name regexes and globs match the same way whether or not they ignore case.
+        Ok(Filters {
+            entry_types: args.entry_types.clone(),
+            not_entry_types: args.not_entry_types.clone(),
+            name_regexes: args.names.iter().chain(&args.inames).cloned().collect(),
+            name_globs: args.globs.iter().chain(&args.iglobs).cloned().collect(),
+            not_names: args.not_names.clone(),
+            path_regexes: args.path_regexes.clone(),
+            sizes: args.sizes.clone(),
+            mtimes: args.mtimes.clone(),
+            newer_than,
+            empty: args.empty,
+            now: SystemTime::now(),
+        })
     }
 
-    Ok(())
-}
+    fn matches(&self, walkdir_entry: &walkdir::DirEntry) -> bool {
+        self.matches_type(walkdir_entry)
+            && self.matches_name(walkdir_entry)
+            && self.matches_path(walkdir_entry)
+            && self.matches_size(walkdir_entry)
+            && self.matches_mtime(walkdir_entry)
+            && self.matches_empty(walkdir_entry)
+    }
 
-fn do_run(args: Args) -> anyhow::Result<()> {
-    let type_filter = |walkdir_entry: &walkdir::DirEntry| -> bool {
-        args.entry_types.is_empty()
-            || args.entry_types.iter().any(|entry_type| match entry_type {
+    // An entry passes when it is of any of the types and of none of the negated types, so that
+    // each --not-type excludes one more type, like a chain of `! -type` in find.
+    fn matches_type(&self, walkdir_entry: &walkdir::DirEntry) -> bool {
+        let is_entry_type = |entry_type: &EntryType| -> bool {
+            match entry_type {
                 EntryType::Link => walkdir_entry.file_type().is_symlink(),
                 EntryType::Dir => walkdir_entry.file_type().is_dir(),
                 EntryType::File => walkdir_entry.file_type().is_file(),
-            })
-    };
+            }
+        };
+
+        (self.entry_types.is_empty() || self.entry_types.iter().any(is_entry_type))
+            && !self.not_entry_types.iter().any(is_entry_type)
+    }
+
+    // An entry passes when its name matches any of the name regexes or any of the globs, and none
+    // of the negated name regexes.
+    fn matches_name(&self, walkdir_entry: &walkdir::DirEntry) -> bool {
+        let file_name = walkdir_entry.file_name().to_string_lossy();
 
-    let name_filter = |walkdir_entry: &walkdir::DirEntry| -> bool {
-        args.names.is_empty()
-            || args
-                .names
+        ((self.name_regexes.is_empty() && self.name_globs.is_empty())
+            || self
+                .name_regexes
                 .iter()
-                .any(|name_regex| name_regex.is_match(&walkdir_entry.file_name().to_string_lossy()))
+                .any(|name_regex| name_regex.is_match(&file_name))
+            || self
+                .name_globs
+                .iter()
+                .any(|name_glob| name_glob.is_match(file_name.as_ref())))
+            && !self
+                .not_names
+                .iter()
+                .any(|name_regex| name_regex.is_match(&file_name))
+    }
+
+    // An entry passes when its whole path, as it is walked rather than as it is printed, matches
+    // any of the path regexes.
+    fn matches_path(&self, walkdir_entry: &walkdir::DirEntry) -> bool {
+        let path = walkdir_entry.path().display().to_string();
+
+        self.path_regexes.is_empty()
+            || self
+                .path_regexes
+                .iter()
+                .any(|path_regex| path_regex.is_match(&path))
+    }
+
+    // An entry passes when it is a regular file whose size satisfies every size predicate. The
+    // sizes of directories and symbolic links depend on the file system, so they never match.
+    fn matches_size(&self, walkdir_entry: &walkdir::DirEntry) -> bool {
+        if self.sizes.is_empty() {
+            return true;
+        }
+
+        if !walkdir_entry.file_type().is_file() {
+            return false;
+        }
+
+        match walkdir_entry.metadata() {
+            Ok(metadata) => self.sizes.iter().all(|size| size.is_match(metadata.len())),
+            Err(e) => {
+                eprintln!("{e}");
+                false
+            }
+        }
+    }
+
+    // An entry passes when its modification time satisfies every time predicate. An entry whose
+    // modification time is not available on the platform is reported and skipped.
+    fn matches_mtime(&self, walkdir_entry: &walkdir::DirEntry) -> bool {
+        if self.mtimes.is_empty() && self.newer_than.is_none() {
+            return true;
+        }
+
+        let modified = walkdir_entry
+            .metadata()
+            .map_err(io::Error::from)
+            .and_then(|metadata| metadata.modified());
+
+        match modified {
+            Ok(modified) => {
+                // A modification time in the future counts as modified just now.
+                let age = self.now.duration_since(modified).unwrap_or_default();
+
+                self.mtimes.iter().all(|mtime| mtime.is_match(age))
+                    && self
+                        .newer_than
+                        .is_none_or(|newer_than| modified > newer_than)
+            }
+            Err(e) => {
+                eprintln!("{}: {e}", walkdir_entry.path().display());
+                false
+            }
+        }
+    }
+
+    // Like find, an entry is empty when it is a file of zero length or a directory without any
+    // entries. Anything else, such as a symbolic link, is never empty.
+    fn matches_empty(&self, walkdir_entry: &walkdir::DirEntry) -> bool {
+        if !self.empty {
+            return true;
+        }
+
+        let file_type = walkdir_entry.file_type();
+        let is_empty = if file_type.is_file() {
+            walkdir_entry
+                .metadata()
+                .map(|metadata| metadata.len() == 0)
+                .map_err(io::Error::from)
+        } else if file_type.is_dir() {
+            fs::read_dir(walkdir_entry.path()).map(|mut dir_entries| dir_entries.next().is_none())
+        } else {
+            Ok(false)
+        };
+
+        is_empty.unwrap_or_else(|e| {
+            eprintln!("{}: {e}", walkdir_entry.path().display());
+            false
+        })
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = match terminate_exec(std::env::args_os()) {
+        Ok(argv) => Args::parse_from(argv),
+        Err(message) => Args::command()
+            .error(clap::error::ErrorKind::ValueValidation, message)
+            .exit(),
+    };
+
+    match do_run(args, BufWriter::new(io::stdout().lock())) {
+        Err(e) => cli_common::exit_with_error(e, 1),
+        // Like find, fail after searching the other paths when a search path does not exist or
+        // a command fails.
+        Ok(false) => std::process::exit(1),
+        Ok(true) => Ok(()),
+    }
+}
+
+// Returns whether all the search paths could be searched and all the commands succeeded.
+fn do_run(args: Args, mut writer: impl Write) -> anyhow::Result<bool> {
+    // Clap has already rejected more than one action.
+    let action = match &args.action_arguments {
+        ActionArguments { print0: true, .. } => Action::Print0,
+        ActionArguments { count: true, .. } => Action::Count,
+        ActionArguments {
+            exec: Some(exec), ..
+        } => Action::Exec(ExecCommand::new(exec)),
+        _ => Action::Print,
     };
 
+    let filters = Filters::new(&args)?;
+
+    // Count the matching entries across all the search paths.
+    let mut match_count = 0;
+
+    let use_color = args.color.should_colorize();
+
+    // Show where a symbolic link points, like `ls -l`. A link that cannot be read is still
+    // printed, without its target.
+    let format_path = |walkdir_entry: &walkdir::DirEntry| -> String {
+        let path = display_path(walkdir_entry.path(), args.relative_to.as_deref());
+        let colored_path = paint_by_type(&path, walkdir_entry.file_type(), use_color);
+
+        if args.show_target && walkdir_entry.path_is_symlink() {
+            match fs::read_link(walkdir_entry.path()) {
+                Ok(target) => format!("{colored_path} -> {}", target.display()),
+                Err(e) => {
+                    eprintln!("{path}: {e}");
+                    colored_path
+                }
+            }
+        } else {
+            colored_path
+        }
+    };
+
+    // Collect the paths for a command that runs once with all of them.
+    let mut batch_paths = vec![];
+
+    // Remember whether any search path was invalid or any command failed for the exit code.
+    let mut has_failed = false;
+
     for path in args.paths {
-        let filtered_entries: Vec<_> = WalkDir::new(path)
+        // Report a search path that does not exist by itself, rather than as a WalkDir error.
+        if let Err(e) = fs::symlink_metadata(&path) {
+            eprintln!("{path}: {e}");
+            has_failed = true;
+            continue;
+        }
+
+        // Like find, depth 0 is the search path itself. The name and type filters only see the
+        // entries within the depth range.
+        // WalkDir detects the file system loops that following links can create, and yields them
+        // as errors, which are reported like any other bad directory.
+        let mut walkdir = WalkDir::new(path)
+            .min_depth(args.min_depth)
+            .follow_links(args.follow);
+        if let Some(max_depth) = args.max_depth {
+            walkdir = walkdir.max_depth(max_depth);
+        }
+
+        // Stream the matching paths one at a time, so that a large tree is never held in memory.
+        let filtered_entries = walkdir
             .into_iter()
             .filter_map(
                 |walkdir_entry: Result<walkdir::DirEntry, _>| match walkdir_entry {
@@ -100,13 +693,996 @@ fn do_run(args: Args) -> anyhow::Result<()> {
                     }
                 },
             )
-            .filter(type_filter)
-            .filter(name_filter)
-            .map(|walkdir_entry| walkdir_entry.path().display().to_string())
+            .filter(|walkdir_entry| filters.matches(walkdir_entry));
+
+        // Sorting needs all the matching entries of the search path at once, so the paths are
+        // only streamed in the traversal order.
+        let filtered_entries: Box<dyn Iterator<Item = walkdir::DirEntry>> = match args.sort {
+            None => Box::new(filtered_entries),
+            Some(sort_key) => {
+                Box::new(sort_entries(filtered_entries.collect(), sort_key).into_iter())
+            }
+        };
+
+        for filtered_entry in filtered_entries {
+            match &action {
+                Action::Print => writeln!(writer, "{}", format_path(&filtered_entry))?,
+                // Terminate every path, including the last one, like `find -print0`.
+                Action::Print0 => write!(writer, "{}\0", format_path(&filtered_entry))?,
+                Action::Count => match_count += 1,
+                // The commands are given the paths as they are walked, which are valid from the
+                // current directory, rather than as they are printed.
+                Action::Exec(exec_command) if exec_command.is_batch => {
+                    batch_paths.push(filtered_entry.into_path())
+                }
+                Action::Exec(exec_command) => {
+                    if !exec_command.run(&[filtered_entry.into_path()]) {
+                        has_failed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    // Like find, a batched command is not run at all when nothing matches, and is run more than
+    // once when the paths do not fit in one command line.
+    if let Action::Exec(exec_command) = &action {
+        for chunk in exec_command.split_batch(&batch_paths, MAX_BATCH_BYTES) {
+            if !exec_command.run(chunk) {
+                has_failed = true;
+            }
+        }
+    }
+
+    if action == Action::Count {
+        writeln!(writer, "{match_count}")?;
+    }
+
+    writer.flush()?;
+
+    Ok(!has_failed)
+}
+
+// Sorts the entries by the key, breaking ties by path. An entry whose metadata cannot be read
+// sorts first.
+fn sort_entries(mut entries: Vec<walkdir::DirEntry>, sort_key: SortKey) -> Vec<walkdir::DirEntry> {
+    match sort_key {
+        SortKey::Name => entries.sort_by(|entry1, entry2| entry1.path().cmp(entry2.path())),
+        SortKey::Size => entries.sort_by_cached_key(|entry| {
+            let size = entry.metadata().ok().map(|metadata| metadata.len());
+            (size, entry.path().to_path_buf())
+        }),
+        SortKey::Mtime => entries.sort_by_cached_key(|entry| {
+            let mtime = entry
+                .metadata()
+                .ok()
+                .and_then(|metadata| metadata.modified().ok());
+            (mtime, entry.path().to_path_buf())
+        }),
+    }
+
+    entries
+}
+
+// Formats a path for printing, stripping the base directory when one is given. A path that is not
+// under the base directory is kept as it is, and the base directory itself is printed as ".".
+fn display_path(path: &Path, base_dir: Option<&Path>) -> String {
+    match base_dir.map(|base_dir| path.strip_prefix(base_dir)) {
+        Some(Ok(relative_path)) if relative_path.as_os_str().is_empty() => String::from("."),
+        Some(Ok(relative_path)) => relative_path.display().to_string(),
+        _ => path.display().to_string(),
+    }
+}
+
+// The SGR escape sequences that ls uses by default.
+const DIR_COLOR: &str = "\x1b[01;34m";
+const LINK_COLOR: &str = "\x1b[01;36m";
+const RESET_COLOR: &str = "\x1b[0m";
+
+// Wraps the path in the color of its type when coloring is on. Regular files are not colored.
+fn paint_by_type(path: &str, file_type: fs::FileType, use_color: bool) -> String {
+    let color = if file_type.is_dir() {
+        DIR_COLOR
+    } else if file_type.is_symlink() {
+        LINK_COLOR
+    } else {
+        ""
+    };
+
+    if use_color && !color.is_empty() {
+        format!("{color}{path}{RESET_COLOR}")
+    } else {
+        path.to_string()
+    }
+}
+
+// Parses a size predicate like find's -size: an optional "+" (greater than) or "-" (less than),
+// a number, and an optional unit suffix.
+fn parse_size(text: &str) -> Result<SizeFilter, String> {
+    let (ordering, size) = parse_comparison(text);
+
+    let (digits, unit_size) = match size.char_indices().last() {
+        Some((index, 'c')) => (&size[..index], 1),
+        Some((index, 'b')) => (&size[..index], 512),
+        Some((index, 'k')) => (&size[..index], 1024),
+        Some((index, 'M')) => (&size[..index], 1024 * 1024),
+        Some((index, 'G')) => (&size[..index], 1024 * 1024 * 1024),
+        _ => (size, 512),
+    };
+
+    let unit_count = digits
+        .parse()
+        .map_err(|_| format!("invalid size \"{text}\"; use [+|-]N[c|k|M|G|b]"))?;
+
+    Ok(SizeFilter {
+        ordering,
+        unit_count,
+        unit_size,
+    })
+}
+
+// Parses a modification time predicate like find's -mtime: an optional "+" (more than) or "-"
+// (less than) and a number of days.
+fn parse_mtime(text: &str) -> Result<MtimeFilter, String> {
+    let (ordering, days) = parse_comparison(text);
+
+    let days = days
+        .parse()
+        .map_err(|_| format!("invalid number of days \"{text}\"; use [+|-]N"))?;
+
+    Ok(MtimeFilter { ordering, days })
+}
+
+// Splits the "+" (greater than) or "-" (less than) prefix of a numeric predicate from its value.
+// Without a prefix, the value must be equal.
+fn parse_comparison(text: &str) -> (std::cmp::Ordering, &str) {
+    if let Some(value) = text.strip_prefix('+') {
+        (std::cmp::Ordering::Greater, value)
+    } else if let Some(value) = text.strip_prefix('-') {
+        (std::cmp::Ordering::Less, value)
+    } else {
+        (std::cmp::Ordering::Equal, text)
+    }
+}
+
+// Compiles a name regex that ignores case distinctions.
+fn parse_iname(pattern: &str) -> Result<regex::Regex, regex::Error> {
+    regex::RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()
+}
+
+// Compiles a shell-style glob pattern.
+fn parse_glob(pattern: &str) -> Result<globset::GlobMatcher, globset::Error> {
+    Ok(globset::Glob::new(pattern)?.compile_matcher())
+}
+
+// Compiles a shell-style glob pattern that ignores case distinctions.
+fn parse_iglob(pattern: &str) -> Result<globset::GlobMatcher, globset::Error> {
+    Ok(globset::GlobBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()?
+        .compile_matcher())
+}
+
+// Unit tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs findr over dir with the flags, and returns the paths relative to dir, sorted by name
+    // unless the flags choose another key.
+    fn run_findr(dir: &Path, flags: &[&str]) -> Vec<String> {
+        run_findr_status(dir, flags).1
+    }
+
+    // Like run_findr, but also returns whether the run succeeded.
+    fn run_findr_status(dir: &Path, flags: &[&str]) -> (bool, Vec<String>) {
+        let dirname = dir.display().to_string();
+        let mut argv = vec!["findr", &dirname, "--relative-to", &dirname];
+        if !flags.iter().any(|flag| flag.starts_with("--sort")) {
+            argv.push("--sort");
+        }
+        argv.extend_from_slice(flags);
+
+        let argv = terminate_exec(argv.into_iter().map(OsString::from)).unwrap();
+        let mut output = vec![];
+        let is_success = do_run(Args::parse_from(argv), &mut output).unwrap();
+        let lines = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(String::from)
             .collect();
+        (is_success, lines)
+    }
+
+    #[test]
+    fn test_display_path() {
+        let base_dir = Path::new("/data/project");
+
+        // Paths under the base directory are stripped.
+        assert_eq!(
+            display_path(Path::new("/data/project/src/main.rs"), Some(base_dir)),
+            "src/main.rs"
+        );
+        assert_eq!(
+            display_path(Path::new("/data/project"), Some(base_dir)),
+            "."
+        );
+
+        // Paths outside the base directory are unchanged.
+        assert_eq!(
+            display_path(Path::new("/data/project2/a.txt"), Some(base_dir)),
+            "/data/project2/a.txt"
+        );
+        assert_eq!(
+            display_path(Path::new("/other/a.txt"), Some(base_dir)),
+            "/other/a.txt"
+        );
+
+        // Without a base directory, the path is printed as it is.
+        assert_eq!(display_path(Path::new("./a/b.txt"), None), "./a/b.txt");
+    }
+
+    #[test]
+    fn test_filters() {
+        // dir/a.txt, dir/b.rs, dir/sub.txt/
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "").unwrap();
+        std::fs::write(dir.path().join("b.rs"), "").unwrap();
+        std::fs::create_dir(dir.path().join("sub.txt")).unwrap();
+
+        let entry = |filename: &str| -> walkdir::DirEntry {
+            WalkDir::new(dir.path().join(filename))
+                .into_iter()
+                .next()
+                .unwrap()
+                .unwrap()
+        };
+        let filters = |flags: &[&str]| -> Filters {
+            let argv = ["findr"].iter().chain(flags);
+            Filters::new(&Args::parse_from(argv)).unwrap()
+        };
+        let matches = |flags: &[&str]| -> Vec<&str> {
+            let filters = filters(flags);
+            ["a.txt", "b.rs", "sub.txt"]
+                .into_iter()
+                .filter(|filename| filters.matches(&entry(filename)))
+                .collect()
+        };
+
+        // With no conditions, everything matches.
+        assert_eq!(matches(&[]), ["a.txt", "b.rs", "sub.txt"]);
+
+        // Type only
+        assert_eq!(matches(&["-t", "f"]), ["a.txt", "b.rs"]);
+        assert_eq!(matches(&["-t", "d"]), ["sub.txt"]);
+        assert_eq!(matches(&["-t", "f", "d"]), ["a.txt", "b.rs", "sub.txt"]);
+        assert_eq!(matches(&["--not-type", "f"]), ["sub.txt"]);
+
+        // Name only
+        assert_eq!(matches(&["-n", "txt$"]), ["a.txt", "sub.txt"]);
+        assert_eq!(matches(&["-n", "^a", "-n", "rs$"]), ["a.txt", "b.rs"]);
+        assert_eq!(matches(&["--iname", "^B"]), ["b.rs"]);
+        assert_eq!(matches(&["--not-name", "txt$"]), ["b.rs"]);
+
+        // Combined conditions must all be met.
+        assert_eq!(matches(&["-n", "txt$", "-t", "f"]), ["a.txt"]);
+        assert_eq!(matches(&["-n", "txt$", "--not-type", "f"]), ["sub.txt"]);
+        assert_eq!(matches(&["-n", "txt$", "-t", "f", "--empty"]), ["a.txt"]);
+        assert_eq!(matches(&["-n", "rs$", "-t", "d"]), Vec::<&str>::new());
+
+        // The --newer reference file must exist.
+        let argv = ["findr", "--newer", "no-such-file"];
+        assert!(Filters::new(&Args::parse_from(argv)).is_err());
+    }
+
+    #[test]
+    fn test_actions() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "").unwrap();
+        std::fs::write(dir.path().join("b.txt"), "").unwrap();
+        let dirname = dir.path().display().to_string();
+
+        let run = |flags: &[&str]| run_findr(dir.path(), &[&["--type", "f"], flags].concat());
+
+        assert_eq!(run(&[]), ["a.txt", "b.txt"]);
+        assert_eq!(run(&["--print"]), run(&[]));
+
+        // Every path is terminated by a NUL character instead of a newline.
+        assert_eq!(run(&["--print0"]), ["a.txt\0b.txt\0"]);
+
+        // Nothing is printed when nothing matches.
+        assert!(run(&["--print0", "-n", "^z"]).is_empty());
+        assert!(run(&["-n", "^z"]).is_empty());
+
+        // Both search paths are counted together.
+        assert_eq!(run(&["--count"]), ["2"]);
+        assert_eq!(run(&["--count", &dirname]), ["4"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_show_target() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "").unwrap();
+        std::os::unix::fs::symlink("a.txt", dir.path().join("link")).unwrap();
+        std::os::unix::fs::symlink("missing.txt", dir.path().join("dangling")).unwrap();
+        let run = |flags: &[&str]| run_findr(dir.path(), flags);
+
+        // Only the symbolic links get their targets, even the dangling one.
+        assert_eq!(
+            run(&["--show-target"]),
+            [".", "a.txt", "dangling -> missing.txt", "link -> a.txt"]
+        );
+        assert_eq!(
+            run(&["--show-target", "-t", "l"]),
+            ["dangling -> missing.txt", "link -> a.txt"]
+        );
+
+        // Without the flag, the links are printed like any other path.
+        assert_eq!(run(&["-t", "l"]), ["dangling", "link"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_color() {
+        // dir/a.txt, dir/sub/, dir/link -> a.txt
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "").unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::os::unix::fs::symlink("a.txt", dir.path().join("link")).unwrap();
+
+        let run = |flags: &[&str]| run_findr(dir.path(), flags);
+
+        let colored = [
+            "\x1b[01;34m.\x1b[0m",
+            "a.txt",
+            "\x1b[01;36mlink\x1b[0m",
+            "\x1b[01;34msub\x1b[0m",
+        ];
+        assert_eq!(run(&["--color=always"]), colored);
+        assert_eq!(run(&["--color"]), colored);
+
+        // Only the path of a link is colored, not its target.
+        assert_eq!(
+            run(&["--color", "--show-target", "-t", "l"]),
+            ["\x1b[01;36mlink\x1b[0m -> a.txt"]
+        );
+
+        // The paths are plain by default, like ls.
+        let plain = [".", "a.txt", "link", "sub"];
+        assert_eq!(run(&[]), plain);
+        assert_eq!(run(&["--color=never"]), plain);
+    }
+
+    #[test]
+    fn test_actions_conflict() {
+        for flags in [
+            ["--count", "--print0"],
+            ["--print", "--count"],
+            ["--print0", "--print"],
+        ] {
+            let error = Args::try_parse_from(["findr", "."].iter().chain(&flags)).unwrap_err();
+            assert_eq!(
+                error.kind(),
+                clap::error::ErrorKind::ArgumentConflict,
+                "{flags:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_exec_command() {
+        let exec_command = |args: &[&str]| {
+            ExecCommand::new(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+        };
+        let paths = [PathBuf::from("a.txt"), PathBuf::from("b.txt")];
+
+        // Every "{}" is replaced, even within an argument.
+        assert_eq!(
+            exec_command(&["mv", "{}", "{}.bak"]).command_line(&paths[..1]),
+            ["mv", "a.txt", "a.txt.bak"]
+        );
+        assert_eq!(
+            exec_command(&["echo", "+"]).command_line(&paths[..1]),
+            ["echo", "+"]
+        );
+
+        // With "{} +", the paths are appended to the command.
+        let batch_command = exec_command(&["echo", "-n", "{}", "+"]);
+        assert!(batch_command.is_batch);
+        assert_eq!(
+            batch_command.command_line(&paths),
+            ["echo", "-n", "a.txt", "b.txt"]
+        );
+
+        // A batch is split when the command line would be too long. With a pointer and a NUL
+        // each, the command takes 13 bytes and every path 14, so 42 bytes fit two paths.
+        let paths = [
+            PathBuf::from("a.txt"),
+            PathBuf::from("b.txt"),
+            PathBuf::from("c.txt"),
+        ];
+        let batch_command = exec_command(&["cat1", "{}", "+"]);
+        assert_eq!(
+            batch_command.split_batch(&paths, 42),
+            [&paths[..2], &paths[2..]]
+        );
+        assert_eq!(batch_command.split_batch(&paths, 56), [&paths[..]]);
+        assert_eq!(
+            batch_command.split_batch(&paths, 1),
+            [&paths[..1], &paths[1..2], &paths[2..]]
+        );
+        assert!(batch_command.split_batch(&[], 56).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exec() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "").unwrap();
+        std::fs::write(dir.path().join("b.log"), "").unwrap();
+
+        let run = |flags: &[&str]| run_findr_status(dir.path(), &[&["-t", "f"], flags].concat());
+
+        // The command runs once per path, and findr itself prints nothing.
+        assert_eq!(
+            run(&["--exec", "cp", "{}", "{}.copy", ";", "-n", "txt$"]),
+            (true, vec![])
+        );
+        assert!(dir.path().join("a.txt.copy").exists());
+        assert!(!dir.path().join("b.log.copy").exists());
+
+        // A batched command runs once with all the paths.
+        assert!(run(&["-n", "copy$", "--exec", "rm", "{}", "+"]).0);
+        assert!(!dir.path().join("a.txt.copy").exists());
+
+        // A failing command, or one that cannot be started, fails the whole run.
+        assert!(!run(&["--exec", "false", ";"]).0);
+        assert!(!run(&["--exec", "false", "{}", "+"]).0);
+        assert!(!run(&["--exec", "findr-no-such-command", ";"]).0);
+
+        // A batched command is not run when nothing matches.
+        assert!(run(&["-n", "^z", "--exec", "false", "{}", "+"]).0);
+
+        // "{} +" ends the command, so the options after it still filter the paths.
+        assert!(run(&["--exec", "false", "{}", "+", "-n", "^z"]).0);
+        assert!(!run(&["--exec", "false", "{}", "+", "-n", "txt$"]).0);
+
+        // A command must end with ";" or "{} +".
+        let terminate = |argv: &[&str]| terminate_exec(argv.iter().map(OsString::from));
+        assert!(terminate(&["findr", "--exec", "echo", "{}"]).is_err());
+        assert!(terminate(&["findr", "--exec", "echo", "+"]).is_err());
+
+        // So must a command come before the terminator.
+        assert!(terminate(&["findr", "--exec", "{}", "+"]).is_err());
+        assert!(terminate(&["findr", "--exec", ";", "-t", "f"]).is_err());
+        assert_eq!(
+            terminate(&["findr", "--exec", "echo", "{}", "+", "-t", "f"]).unwrap(),
+            ["findr", "--exec", "echo", "{}", "+", ";", "-t", "f"]
+        );
 
-        println!("{}", filtered_entries.join("\n"));
+        // --exec is an action of its own.
+        assert!(Args::try_parse_from(["findr", "--count", "--exec", "true", ";"]).is_err());
     }
 
-    Ok(())
+    #[test]
+    fn test_depth() {
+        // dir/a.txt, dir/sub/b.txt, dir/sub/deeper/c.txt
+        let dir = tempfile::tempdir().unwrap();
+        let deeper_dir = dir.path().join("sub").join("deeper");
+        std::fs::create_dir_all(&deeper_dir).unwrap();
+        std::fs::write(dir.path().join("a.txt"), "").unwrap();
+        std::fs::write(dir.path().join("sub").join("b.txt"), "").unwrap();
+        std::fs::write(deeper_dir.join("c.txt"), "").unwrap();
+
+        let count = |flags: &[&str]| run_findr(dir.path(), &[&["--count"], flags].concat());
+
+        assert_eq!(count(&[]), ["6"]);
+
+        // Depth 0 is the search path itself.
+        assert_eq!(count(&["--max-depth", "0"]), ["1"]);
+        assert_eq!(count(&["--max-depth", "1"]), ["3"]);
+        assert_eq!(count(&["--max-depth", "2"]), ["5"]);
+        assert_eq!(count(&["--min-depth", "1"]), ["5"]);
+        assert_eq!(count(&["--min-depth", "2"]), ["3"]);
+        assert_eq!(count(&["--min-depth", "1", "--max-depth", "1"]), ["2"]);
+
+        // The other filters apply within the depth range.
+        assert_eq!(count(&["--max-depth", "2", "-t", "f"]), ["2"]);
+        assert_eq!(count(&["--min-depth", "2", "-n", "txt$"]), ["2"]);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        let size = |ordering, unit_count, unit_size| SizeFilter {
+            ordering,
+            unit_count,
+            unit_size,
+        };
+        use std::cmp::Ordering::*;
+
+        assert_eq!(parse_size("+1M"), Ok(size(Greater, 1, 1024 * 1024)));
+        assert_eq!(parse_size("-100c"), Ok(size(Less, 100, 1)));
+        assert_eq!(parse_size("3k"), Ok(size(Equal, 3, 1024)));
+        assert_eq!(parse_size("2G"), Ok(size(Equal, 2, 1024 * 1024 * 1024)));
+        assert_eq!(parse_size("2b"), Ok(size(Equal, 2, 512)));
+        assert_eq!(parse_size("2"), Ok(size(Equal, 2, 512)));
+
+        for text in ["", "+", "k", "1x", "--1", "1.5k", "+-1"] {
+            assert!(parse_size(text).is_err(), "{text}");
+        }
+
+        // Sizes are rounded up to whole units before comparing.
+        assert!(size(Equal, 1, 1024).is_match(1));
+        assert!(size(Equal, 1, 1024).is_match(1024));
+        assert!(!size(Equal, 1, 1024).is_match(1025));
+        assert!(size(Less, 1, 1024 * 1024).is_match(0));
+        assert!(!size(Less, 1, 1024 * 1024).is_match(1));
+        assert!(size(Greater, 100, 1).is_match(101));
+        assert!(!size(Greater, 100, 1).is_match(100));
+    }
+
+    #[test]
+    fn test_size_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("empty.txt"), "").unwrap();
+        std::fs::write(dir.path().join("small.txt"), "x".repeat(100)).unwrap();
+        std::fs::write(dir.path().join("large.txt"), "x".repeat(5000)).unwrap();
+
+        let run = |flags: &[&str]| run_findr(dir.path(), &[&["-t", "f"], flags].concat());
+
+        assert_eq!(run(&["--size", "100c"]), ["small.txt"]);
+        assert_eq!(run(&["--size", "-100c"]), ["empty.txt"]);
+        assert_eq!(run(&["--size", "+100c"]), ["large.txt"]);
+        assert_eq!(run(&["--size", "-1k"]), ["empty.txt"]);
+        assert_eq!(run(&["--size", "5k"]), ["large.txt"]);
+
+        // Directories never match, even when their size would.
+        assert_eq!(
+            run_findr(dir.path(), &["--size", "+0c"]),
+            ["large.txt", "small.txt"]
+        );
+
+        // Every size predicate must hold.
+        assert_eq!(
+            run(&["--size", "+0", "--size", "-1k"]),
+            Vec::<String>::new()
+        );
+        assert_eq!(run(&["--size", "+0", "--size", "-2k"]), ["small.txt"]);
+    }
+
+    #[test]
+    fn test_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("empty.txt"), "").unwrap();
+        std::fs::write(dir.path().join("full.txt"), "x").unwrap();
+        std::fs::create_dir(dir.path().join("empty_dir")).unwrap();
+        std::fs::create_dir(dir.path().join("full_dir")).unwrap();
+        std::fs::write(dir.path().join("full_dir").join("empty2.txt"), "").unwrap();
+
+        let run = |flags: &[&str]| run_findr(dir.path(), &[&["--empty"], flags].concat());
+
+        assert_eq!(run(&[]), ["empty.txt", "empty_dir", "full_dir/empty2.txt"]);
+
+        // The type filter narrows the empty entries down further.
+        assert_eq!(run(&["-t", "d"]), ["empty_dir"]);
+        assert_eq!(run(&["-t", "f"]), ["empty.txt", "full_dir/empty2.txt"]);
+    }
+
+    #[test]
+    fn test_sort() {
+        // dir/b.txt, dir/a/c.txt, dir/a/a.txt
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("a")).unwrap();
+        std::fs::write(dir.path().join("b.txt"), "xx").unwrap();
+        std::fs::write(dir.path().join("a").join("c.txt"), "xxx").unwrap();
+        std::fs::write(dir.path().join("a").join("a.txt"), "x").unwrap();
+
+        // Make the modification times differ from the names and the sizes.
+        let now = std::time::SystemTime::now();
+        for (filename, age) in [("b.txt", 20), ("a/c.txt", 30), ("a/a.txt", 10)] {
+            let mtime = now - std::time::Duration::from_secs(age);
+            let file = std::fs::File::options()
+                .write(true)
+                .open(dir.path().join(filename))
+                .unwrap();
+            file.set_modified(mtime).unwrap();
+        }
+
+        let run = |flags: &[&str]| run_findr(dir.path(), flags);
+
+        let expected = [".", "a", "a/a.txt", "a/c.txt", "b.txt"];
+        assert_eq!(run(&["--sort"]), expected);
+        assert_eq!(run(&["--sort=name"]), expected);
+        assert_eq!(
+            run(&["--sort=size", "-t", "f"]),
+            ["a/a.txt", "b.txt", "a/c.txt"]
+        );
+        assert_eq!(
+            run(&["--sort=mtime", "-t", "f"]),
+            ["a/c.txt", "b.txt", "a/a.txt"]
+        );
+
+        // A bare --sort does not take the next argument as its key.
+        assert_eq!(run(&["--sort", "-t", "f"]), ["a/a.txt", "a/c.txt", "b.txt"]);
+        assert!(Args::try_parse_from(["findr", "--sort=color"]).is_err());
+    }
+
+    #[test]
+    fn test_sort_by_path_components() {
+        // dir1/a/x.txt, dir1/a-b.txt, dir2/c.txt
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("dir1").join("a")).unwrap();
+        std::fs::create_dir(dir.path().join("dir2")).unwrap();
+        std::fs::write(dir.path().join("dir1").join("a").join("x.txt"), "").unwrap();
+        std::fs::write(dir.path().join("dir1").join("a-b.txt"), "").unwrap();
+        std::fs::write(dir.path().join("dir2").join("c.txt"), "").unwrap();
+        let dirname = dir.path().display().to_string();
+        let dir1 = dir.path().join("dir1").display().to_string();
+        let dir2 = dir.path().join("dir2").display().to_string();
+
+        // The search paths keep their order, and each one is sorted on its own.
+        let argv = [
+            "findr",
+            &dir2,
+            &dir1,
+            "--relative-to",
+            &dirname,
+            "-t",
+            "f",
+            "--sort",
+        ];
+        let mut output = vec![];
+        do_run(Args::parse_from(argv), &mut output).unwrap();
+
+        // "a/x.txt" comes before "a-b.txt" even though '/' sorts after '-' as a character.
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "dir2/c.txt\ndir1/a/x.txt\ndir1/a-b.txt\n"
+        );
+    }
+
+    #[test]
+    fn test_negation() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("a.txt"), "").unwrap();
+        std::fs::write(dir.path().join("b.csv"), "").unwrap();
+        std::fs::write(dir.path().join("sub").join("c.md"), "").unwrap();
+
+        assert_eq!(
+            run_findr(dir.path(), &["--not-name", r"\.txt$"]),
+            [".", "b.csv", "sub", "sub/c.md"]
+        );
+
+        // Every negation excludes more entries.
+        assert_eq!(
+            run_findr(
+                dir.path(),
+                &["--not-name", r"\.txt$", "--not-name", "^sub$", "-t", "f"]
+            ),
+            ["b.csv", "sub/c.md"]
+        );
+        assert_eq!(run_findr(dir.path(), &["--not-type", "f"]), [".", "sub"]);
+        assert_eq!(
+            run_findr(dir.path(), &["--not-type", "f", "--not-type", "d"]),
+            Vec::<String>::new()
+        );
+
+        // A negation narrows down the positive filters.
+        assert_eq!(
+            run_findr(dir.path(), &["-n", "^[ab]", "--not-name", "csv"]),
+            ["a.txt"]
+        );
+        assert_eq!(
+            run_findr(dir.path(), &["-t", "f", "d", "--not-type", "d"]),
+            ["a.txt", "b.csv", "sub/c.md"]
+        );
+    }
+
+    #[test]
+    fn test_mtime() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        for (filename, days) in [("today.txt", 0), ("week.txt", 7), ("month.txt", 30)] {
+            let path = dir.path().join(filename);
+            std::fs::write(&path, "").unwrap();
+
+            // Keep an hour away from the day boundaries.
+            let mtime = now - Duration::from_secs(days * SECONDS_PER_DAY + 3600);
+            filetime::set_file_mtime(&path, filetime::FileTime::from_system_time(mtime)).unwrap();
+        }
+        let dirname = dir.path().display().to_string();
+        let reference = dir.path().join("week.txt").display().to_string();
+
+        let run = |flags: &[&str]| run_findr(dir.path(), &[&["-t", "f"], flags].concat());
+
+        assert_eq!(run(&["--mtime", "0"]), ["today.txt"]);
+        assert_eq!(run(&["--mtime", "7"]), ["week.txt"]);
+        assert_eq!(run(&["--mtime", "-7"]), ["today.txt"]);
+        assert_eq!(run(&["--mtime", "+7"]), ["month.txt"]);
+        assert_eq!(run(&["--mtime", "+0", "--mtime", "-30"]), ["week.txt"]);
+        assert_eq!(run(&["--newer", &reference]), ["today.txt"]);
+        assert_eq!(
+            run(&["--newer", &reference, "--mtime", "+0"]),
+            Vec::<String>::new()
+        );
+
+        // The reference file must exist.
+        let args = Args::parse_from(["findr", &dirname, "--newer", "missing.txt"]);
+        let error = do_run(args, io::sink()).unwrap_err();
+        assert!(error.to_string().starts_with("missing.txt: "));
+
+        for text in ["", "+", "7d", "1.5", "+-1"] {
+            assert!(parse_mtime(text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_mtime_future() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        for (filename, mtime) in [
+            ("future.txt", now + Duration::from_secs(SECONDS_PER_DAY)),
+            ("old.txt", now - Duration::from_secs(2 * SECONDS_PER_DAY)),
+        ] {
+            let path = dir.path().join(filename);
+            std::fs::write(&path, "").unwrap();
+            filetime::set_file_mtime(&path, filetime::FileTime::from_system_time(mtime)).unwrap();
+        }
+        let old = dir.path().join("old.txt").display().to_string();
+
+        let matches = |flags: &[&str]| -> Vec<&str> {
+            let argv = ["findr"].iter().chain(flags);
+            let filters = Filters::new(&Args::parse_from(argv)).unwrap();
+            ["future.txt", "old.txt"]
+                .into_iter()
+                .filter(|filename| {
+                    let entry = WalkDir::new(dir.path().join(filename))
+                        .into_iter()
+                        .next()
+                        .unwrap()
+                        .unwrap();
+                    filters.matches(&entry)
+                })
+                .collect()
+        };
+
+        // A modification time in the future counts as modified just now.
+        assert_eq!(matches(&["--mtime", "-1"]), ["future.txt"]);
+        assert_eq!(matches(&["--mtime", "0"]), ["future.txt"]);
+        assert_eq!(matches(&["--mtime", "+1"]), ["old.txt"]);
+        assert_eq!(matches(&["--newer", &old]), ["future.txt"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow() {
+        // dir/real/a.txt, dir/link -> real
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("real")).unwrap();
+        std::fs::write(dir.path().join("real").join("a.txt"), "").unwrap();
+        std::os::unix::fs::symlink("real", dir.path().join("link")).unwrap();
+
+        // Without -L, the link is not descended into.
+        assert_eq!(
+            run_findr(dir.path(), &[]),
+            [".", "link", "real", "real/a.txt"]
+        );
+        assert_eq!(run_findr(dir.path(), &["-t", "l"]), ["link"]);
+
+        // With -L, the link is reported as the directory it points to.
+        assert_eq!(
+            run_findr(dir.path(), &["-L"]),
+            [".", "link", "link/a.txt", "real", "real/a.txt"]
+        );
+        assert_eq!(
+            run_findr(dir.path(), &["--follow", "-t", "d"]),
+            [".", "link", "real"]
+        );
+        assert_eq!(
+            run_findr(dir.path(), &["--follow", "-t", "l"]),
+            Vec::<String>::new()
+        );
+
+        // A link to an ancestor is not followed forever.
+        std::os::unix::fs::symlink("..", dir.path().join("real").join("up")).unwrap();
+        assert_eq!(
+            run_findr(dir.path(), &["-L", "-t", "f"]),
+            ["link/a.txt", "real/a.txt"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow_outside_search_path() {
+        // outside/b.txt, dir/link -> outside
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("b.txt"), "").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("link")).unwrap();
+
+        // The files behind the link are found only when following it.
+        assert_eq!(run_findr(dir.path(), &["-t", "f"]), Vec::<String>::new());
+        assert_eq!(
+            run_findr(dir.path(), &["-t", "f", "--follow"]),
+            ["link/b.txt"]
+        );
+    }
+
+    #[test]
+    fn test_path() {
+        // dir/src/main.rs, dir/src/bin/tool.rs, dir/tests/cli.rs
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src").join("bin")).unwrap();
+        std::fs::create_dir(dir.path().join("tests")).unwrap();
+        std::fs::write(dir.path().join("src").join("main.rs"), "").unwrap();
+        std::fs::write(dir.path().join("src").join("bin").join("tool.rs"), "").unwrap();
+        std::fs::write(dir.path().join("tests").join("cli.rs"), "").unwrap();
+
+        assert_eq!(
+            run_findr(dir.path(), &["--path", r"/src/.*\.rs$"]),
+            ["src/bin/tool.rs", "src/main.rs"]
+        );
+        assert_eq!(
+            run_findr(
+                dir.path(),
+                &["--path", r"/src/[^/]*\.rs$", "--path", "/tests/"]
+            ),
+            ["src/main.rs", "tests/cli.rs"]
+        );
+
+        // The path is combined with the other filters.
+        assert_eq!(
+            run_findr(dir.path(), &["--path", "/src", "-t", "d"]),
+            ["src", "src/bin"]
+        );
+        assert_eq!(
+            run_findr(dir.path(), &["--path", "/src/", "-n", "^main"]),
+            ["src/main.rs"]
+        );
+    }
+
+    #[test]
+    fn test_missing_path() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "").unwrap();
+        let dirname = dir.path().display().to_string();
+        let missing = dir.path().join("missing").display().to_string();
+
+        // The other paths are still searched.
+        let mut output = vec![];
+        let args = Args::parse_from(["findr", &missing, &dirname, "-t", "f", "--count"]);
+        assert!(!do_run(args, &mut output).unwrap());
+        assert_eq!(String::from_utf8(output).unwrap(), "1\n");
+
+        let args = Args::parse_from(["findr", &dirname]);
+        assert!(do_run(args, io::sink()).unwrap());
+    }
+
+    #[test]
+    fn test_max_depth_top_level() {
+        // dir/a.txt, dir/sub/b.txt
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("a.txt"), "").unwrap();
+        std::fs::write(dir.path().join("sub").join("b.txt"), "").unwrap();
+
+        assert_eq!(
+            run_findr(dir.path(), &["--max-depth", "1"]),
+            [".", "a.txt", "sub"]
+        );
+        assert_eq!(
+            run_findr(dir.path(), &["--max-depth", "1", "--min-depth", "1"]),
+            ["a.txt", "sub"]
+        );
+        assert_eq!(
+            run_findr(dir.path(), &["--max-depth", "1", "-t", "f"]),
+            ["a.txt"]
+        );
+        assert_eq!(
+            run_findr(dir.path(), &["--max-depth", "1", "-n", "txt$"]),
+            ["a.txt"]
+        );
+    }
+
+    #[test]
+    fn test_iname() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("README"), "").unwrap();
+        std::fs::write(dir.path().join("readme.md"), "").unwrap();
+        std::fs::write(dir.path().join("main.rs"), "").unwrap();
+
+        assert_eq!(
+            run_findr(dir.path(), &["--iname", "readme"]),
+            ["README", "readme.md"]
+        );
+        assert_eq!(run_findr(dir.path(), &["--iname", "^README$"]), ["README"]);
+        assert_eq!(run_findr(dir.path(), &["-n", "readme"]), ["readme.md"]);
+
+        // The names are matched like any other name.
+        assert_eq!(
+            run_findr(dir.path(), &["--iname", "^readme$", "-n", "rs$"]),
+            ["README", "main.rs"]
+        );
+        assert!(parse_iname("(").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_print0_newline_in_name() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("two\nlines.txt"), "").unwrap();
+        std::fs::write(dir.path().join("plain.txt"), "").unwrap();
+        let dirname = dir.path().display().to_string();
+
+        let argv = [
+            "findr",
+            &dirname,
+            "--relative-to",
+            &dirname,
+            "-t",
+            "f",
+            "--sort",
+            "--print0",
+        ];
+        let mut output = vec![];
+        do_run(Args::parse_from(argv), &mut output).unwrap();
+
+        // The newline stays inside its path, and only the NULs separate the paths.
+        assert_eq!(output, b"plain.txt\0two\nlines.txt\0");
+    }
+
+    #[test]
+    fn test_glob() {
+        // dir/a.txt, dir/b.TXT, dir/c.txt.bak, dir/sub/d.txt
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        for filename in ["a.txt", "b.TXT", "c.txt.bak", "sub/d.txt"] {
+            std::fs::write(dir.path().join(filename), "").unwrap();
+        }
+
+        // The whole name must match, and case matters.
+        assert_eq!(
+            run_findr(dir.path(), &["-g", "*.txt"]),
+            ["a.txt", "sub/d.txt"]
+        );
+        assert_eq!(run_findr(dir.path(), &["--glob", "?.TXT"]), ["b.TXT"]);
+        assert_eq!(run_findr(dir.path(), &["-g", "[ab].*"]), ["a.txt", "b.TXT"]);
+
+        // Globs combine with each other, and with name regexes, like repeated -n.
+        assert_eq!(
+            run_findr(dir.path(), &["-g", "*.bak", "-g", "d.*"]),
+            ["c.txt.bak", "sub/d.txt"]
+        );
+        assert_eq!(
+            run_findr(dir.path(), &["-g", "*.bak", "-n", "^a"]),
+            ["a.txt", "c.txt.bak"]
+        );
+        assert_eq!(
+            run_findr(dir.path(), &["-g", "*.txt", "--not-name", "^d"]),
+            ["a.txt"]
+        );
+
+        assert!(Args::try_parse_from(["findr", "-g", "[a"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_iglob() {
+        // The glob ignores case distinctions in both directions.
+        let iglob = parse_iglob("*.TXT").unwrap();
+        assert!(iglob.is_match("fox.txt"));
+        assert!(iglob.is_match("FOX.TXT"));
+        assert!(!iglob.is_match("fox.csv"));
+
+        let iglob = parse_iglob("f?x.*").unwrap();
+        assert!(iglob.is_match("Fox.txt"));
+        assert!(!iglob.is_match("box.txt"));
+
+        // An unclosed character class is invalid.
+        assert!(parse_iglob("[a").is_err());
+    }
 }
@@ -1,9 +1,13 @@
 use clap::Parser;
+use cli_common::{exit_with_error, open_input, ColorChoice};
 use regex::{Regex, RegexBuilder};
 use std::{
-    fs::{self, File},
-    io::{self, BufRead, BufReader},
+    borrow::Cow,
+    collections::VecDeque,
+    fs,
+    io::{self, BufRead, BufWriter, Write},
     mem,
+    sync::mpsc,
 };
 use walkdir::WalkDir;
 
@@ -14,14 +18,36 @@ struct CliArguments {
     // Positional arguments
     //
     // - The order in which positional arguments are defined is important.
-    /// search pattern
-    #[arg()]
-    pattern: String,
+    /// search pattern, unless given with -e or --pattern-stdin
+    #[arg(required_unless_present_any = ["regexps", "pattern_files", "pattern_stdin"])]
+    pattern: Option<String>,
 
-    /// input file(s)
-    #[arg(value_name = "FILE", default_value = "-")]
+    /// input file(s), STDIN when none are given
+    #[arg(value_name = "FILE")]
     files: Vec<String>,
 
+    /// Use PATTERN for matching; repeat to select lines that match any of the patterns
+    #[arg(
+        short = 'e',
+        long = "regexp",
+        value_name = "PATTERN",
+        action = clap::ArgAction::Append
+    )]
+    regexps: Vec<String>,
+
+    /// Take the patterns from FILE, one per line; repeat to read several files
+    #[arg(
+        short = 'f',
+        long = "file",
+        value_name = "FILE",
+        action = clap::ArgAction::Append
+    )]
+    pattern_files: Vec<String>,
+
+    /// Read the pattern from the first line of STDIN, and treat every positional argument as FILE
+    #[arg(long)]
+    pattern_stdin: bool,
+
     // Optional arguments
     //
     // - The order in which optional arguments are defined does not matter.
@@ -29,10 +55,26 @@ struct CliArguments {
     #[arg(short, long)]
     ignore_case: bool,
 
+    /// Interpret the patterns as fixed strings, not regular expressions
+    #[arg(short = 'F', long)]
+    fixed_strings: bool,
+
+    /// Select only the lines where the pattern matches whole words
+    #[arg(short, long)]
+    word_regexp: bool,
+
     /// Recursive
     #[arg(short, long)]
     recursive: bool,
 
+    /// Search only the files whose names match GLOB when recursing
+    #[arg(long = "include", value_name = "GLOB", value_parser = parse_glob, action = clap::ArgAction::Append)]
+    includes: Vec<globset::GlobMatcher>,
+
+    /// Skip the files whose names match GLOB when recursing
+    #[arg(long = "exclude", value_name = "GLOB", value_parser = parse_glob, action = clap::ArgAction::Append)]
+    excludes: Vec<globset::GlobMatcher>,
+
     /// Print only a count of selected lines per FILE
     #[arg(short, long)]
     count: bool,
@@ -40,95 +82,545 @@ struct CliArguments {
     /// Select non-matching lines
     #[arg(short = 'v', long)]
     invert_match: bool,
+
+    /// Suppress error messages about nonexistent or unreadable files
+    #[arg(short = 's', long)]
+    no_messages: bool,
+
+    /// Print the 0-based byte offset within the input file before each line of output, or before
+    /// each match with -o
+    #[arg(short = 'b', long)]
+    byte_offset: bool,
+
+    /// Print only the matched parts of the matching lines, each on its own line
+    #[arg(short = 'o', long)]
+    only_matching: bool,
+
+    /// Prefix each line of output with the 1-based line number within its input file
+    #[arg(short = 'n', long)]
+    line_number: bool,
+
+    /// Print NUM lines of trailing context after matching lines
+    #[arg(short = 'A', long, value_name = "NUM")]
+    after_context: Option<usize>,
+
+    /// Print NUM lines of leading context before matching lines
+    #[arg(short = 'B', long, value_name = "NUM")]
+    before_context: Option<usize>,
+
+    /// Print NUM lines of output context
+    #[arg(short = 'C', long, value_name = "NUM")]
+    context: Option<usize>,
+
+    /// When to highlight the matches, the filenames, and the line numbers
+    #[arg(long, value_name = "WHEN", value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Truncate printed lines longer than NUM characters
+    #[arg(short = 'M', long, value_name = "NUM")]
+    max_columns: Option<usize>,
+
+    /// Number of files to search at once; 0 means one per CPU
+    #[arg(long, value_name = "NUM", default_value_t = 0)]
+    threads: usize,
 }
 
+// The SGR escape sequences that GNU grep uses by default.
+const MATCH_COLOR: &str = "\x1b[01;31m";
+const FILENAME_COLOR: &str = "\x1b[35m";
+const LINE_NUMBER_COLOR: &str = "\x1b[32m";
+const RESET_COLOR: &str = "\x1b[m";
+
 fn main() {
-    if let Err(e) = do_run(CliArguments::parse()) {
-        eprintln!("{e}");
-        std::process::exit(1);
+    // Lock STDOUT once and buffer all the output so that each matching line does not cost a
+    // separate write system call.
+    let writer = BufWriter::new(io::stdout().lock());
+
+    let result = read_pattern_stdin(CliArguments::parse(), io::stdin().lock())
+        .and_then(|args| do_run(args, writer, io::stderr()));
+
+    match result {
+        Err(e) => exit_with_error(e, 2),
+        Ok(outcome) => std::process::exit(outcome.exit_code()),
+    }
+}
+
+// Represents the result of a search, which decides the exit code like grep
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SearchOutcome {
+    // At least one line was selected
+    Matched,
+    // No line was selected
+    NotMatched,
+    // A file could not be searched, whether or not any line was selected
+    Failed,
+}
+
+// Holds everything that the search of a single file needs to know
+struct SearchSettings<'a> {
+    args: &'a CliArguments,
+    pattern: &'a Regex,
+    file_count: usize,
+    use_color: bool,
+    before_context: usize,
+    after_context: usize,
+}
+
+// Represents what happened while searching a single file
+#[derive(Debug, Default)]
+struct FileSearch {
+    has_matched: bool,
+    has_printed_group: bool,
+    // Errors like unreadable files, reported after the output of the file
+    errors: Vec<String>,
+}
+
+// Adds up the searches of all the files for the exit code
+#[derive(Debug, Default)]
+struct SearchTotals {
+    has_matched: bool,
+    has_failed: bool,
+    has_printed_group: bool,
+}
+
+impl SearchTotals {
+    // Reports the errors of a file search, unless they are suppressed with --no-messages, which
+    // keeps the output of scripted searches over partially unreadable trees clean. The errors
+    // still count for the exit code.
+    fn add(
+        &mut self,
+        search: FileSearch,
+        no_messages: bool,
+        mut error_writer: impl Write,
+    ) -> io::Result<()> {
+        if !no_messages {
+            for message in &search.errors {
+                writeln!(error_writer, "{message}")?;
+            }
+        }
+
+        self.has_failed |= !search.errors.is_empty();
+        self.has_matched |= search.has_matched;
+        self.has_printed_group |= search.has_printed_group;
+
+        Ok(())
+    }
+
+    fn outcome(&self) -> SearchOutcome {
+        if self.has_failed {
+            SearchOutcome::Failed
+        } else if self.has_matched {
+            SearchOutcome::Matched
+        } else {
+            SearchOutcome::NotMatched
+        }
     }
 }
 
-fn do_run(args: CliArguments) -> anyhow::Result<()> {
+impl SearchOutcome {
+    fn exit_code(self) -> i32 {
+        match self {
+            SearchOutcome::Matched => 0,
+            SearchOutcome::NotMatched => 1,
+            SearchOutcome::Failed => 2,
+        }
+    }
+}
+
+impl CliArguments {
+    // Returns the patterns and the files to search. When the patterns are given with -e or
+    // --pattern-stdin, clap has assigned the first file to the pattern positional, so it is moved
+    // back to the front of the files. STDIN is read when no files are given, which is decided here
+    // rather than with a default value so that an explicit "-" can be told apart.
+    fn patterns_and_files(&self) -> (Vec<String>, Vec<String>) {
+        let (patterns, files): (Vec<String>, Vec<String>) =
+            if self.regexps.is_empty() && !self.pattern_stdin {
+                (self.pattern.iter().cloned().collect(), self.files.clone())
+            } else {
+                let files = self.pattern.iter().chain(&self.files).cloned().collect();
+                (self.regexps.clone(), files)
+            };
+
+        if files.is_empty() {
+            (patterns, vec![String::from("-")])
+        } else {
+            (patterns, files)
+        }
+    }
+}
+
+// With --pattern-stdin, takes one more pattern from the first line of the reader.
+fn read_pattern_stdin(
+    mut args: CliArguments,
+    mut reader: impl BufRead,
+) -> anyhow::Result<CliArguments> {
+    if !args.pattern_stdin {
+        return Ok(args);
+    }
+
+    // STDIN is already taken by the pattern, so there is nothing left to search in it.
+    let (_, files) = args.patterns_and_files();
+    if files.iter().any(|filename| filename == "-") {
+        anyhow::bail!("--pattern-stdin cannot be used with STDIN (\"-\") as FILE");
+    }
+
+    // An empty pattern would match every line, so a missing one is an error.
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        anyhow::bail!("--pattern-stdin: no pattern on STDIN");
+    }
+    args.regexps
+        .push(line.trim_end_matches(['\r', '\n']).to_string());
+
+    Ok(args)
+}
+
+fn do_run(
+    args: CliArguments,
+    mut writer: impl Write,
+    mut error_writer: impl Write,
+) -> anyhow::Result<SearchOutcome> {
+    let (mut patterns, files) = args.patterns_and_files();
+
+    // Add the patterns of the -f files to the others.
+    for pattern_file in &args.pattern_files {
+        patterns.extend(read_pattern_file(pattern_file)?);
+    }
+
+    // Escape the regex metacharacters of every pattern for --fixed-strings, so that the patterns
+    // are matched literally by the rest of the pipeline.
+    if args.fixed_strings {
+        patterns = patterns
+            .iter()
+            .map(|pattern_text| regex::escape(pattern_text))
+            .collect();
+    }
+
+    // Check each pattern on its own so that the error points at the one that is invalid.
+    for pattern_text in &patterns {
+        if Regex::new(pattern_text).is_err() {
+            anyhow::bail!(r#"Invalid pattern "{}""#, pattern_text);
+        }
+    }
+
+    // Combine the patterns into a single alternation, so a line matches when any of them does.
+    // Each one is grouped so that its own alternations stay apart from the others.
+    let pattern_text = patterns
+        .iter()
+        .map(|pattern_text| format!("(?:{pattern_text})"))
+        .collect::<Vec<_>>()
+        .join("|");
+
+    // Like GNU grep, an empty patterns file matches nothing rather than every line.
+    let pattern_text = if patterns.is_empty() {
+        r"[^\s\S]"
+    } else {
+        pattern_text.as_str()
+    };
+
+    // Surround the pattern with word boundaries for --word-regexp. The non-capturing group keeps
+    // an alternation like "a|b" bounded as a whole.
+    let regex_source = if args.word_regexp {
+        Cow::Owned(format!(r"\b(?:{})\b", pattern_text))
+    } else {
+        Cow::Borrowed(pattern_text)
+    };
+
     // A RegexBuilder allows for non-default configuration like case-insensitive matching.
-    let pattern = RegexBuilder::new(&args.pattern)
+    let pattern = RegexBuilder::new(&regex_source)
         .case_insensitive(args.ignore_case)
         // RegexBuilder::build rejects any pattern that is not a valid regular expression. There
         // are many syntaxes for writing regular expressions.
         .build()
         // If build returns an error, create an error message stating that the given pattern is
         // invalid.
-        .map_err(|_| anyhow::anyhow!(r#"Invalid pattern "{}""#, args.pattern))?;
+        .map_err(|_| anyhow::anyhow!(r#"Invalid pattern "{}""#, pattern_text))?;
 
     // println!(r#"pattern "{pattern}""#);
 
-    let entries = find_files(&args.files, args.recursive);
+    let entries = find_files(&files, args.recursive, &args.includes, &args.excludes);
     let file_count = entries.len();
 
-    // Handle the printing of the output with or without the filenames given the number of input
-    // files.
-    let print_result_row = |fname: &str, text: &str| {
-        if file_count > 1 {
-            print!("{fname}:{text}");
-        } else {
-            print!("{text}");
-        }
+    let use_color = args.color.should_colorize();
+
+    // The -A and -B options take precedence over -C. The context lines do not affect the count.
+    let (before_context, after_context) = if args.count {
+        (0, 0)
+    } else {
+        (
+            args.before_context.or(args.context).unwrap_or(0),
+            args.after_context.or(args.context).unwrap_or(0),
+        )
     };
 
-    for entry in entries {
+    let settings = SearchSettings {
+        args: &args,
+        pattern: &pattern,
+        file_count,
+        use_color,
+        before_context,
+        after_context,
+    };
+
+    // Like GNU grep, the groups of lines of different files are separated as well when there is
+    // context, which requires remembering whether any group has been printed.
+    let has_context = before_context > 0 || after_context > 0;
+    let mut totals = SearchTotals::default();
+
+    let search_entry = |entry: &anyhow::Result<String>,
+                        is_after_group: bool,
+                        writer: &mut dyn Write|
+     -> anyhow::Result<FileSearch> {
         match entry {
-            Err(e) => {
-                // Print errors like nonexistent files to STDERR.
-                eprintln!("{e}")
+            Err(e) => Ok(FileSearch {
+                errors: vec![e.to_string()],
+                ..Default::default()
+            }),
+            Ok(filename) => search_file(filename, &settings, is_after_group, writer),
+        }
+    };
+
+    if file_count == 1 || args.threads == 1 {
+        // Search the files one at a time straight into the writer, so that the lines are printed
+        // as soon as they are found.
+        for entry in &entries {
+            let is_after_group = has_context && totals.has_printed_group;
+            let search = search_entry(entry, is_after_group, &mut writer)?;
+            totals.add(search, args.no_messages, &mut error_writer)?;
+        }
+    } else {
+        // Otherwise, the files are searched in parallel, each one into its own buffer. The buffers
+        // are printed in the order of the files as soon as each one is complete, so that the
+        // output does not depend on which thread finishes first. Only a window of files is
+        // searched ahead of the one being printed, which bounds the buffered output.
+        let thread_pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(args.threads)
+            .build()?;
+        let window = thread_pool.current_num_threads() * 4;
+
+        std::thread::scope(|thread_scope| -> anyhow::Result<()> {
+            let (sender, receiver) = mpsc::sync_channel(window);
+            let (entries, search_entry) = (&entries, &search_entry);
+
+            thread_scope.spawn(move || {
+                thread_pool.in_place_scope(|pool_scope| {
+                    for entry in entries {
+                        let (result_sender, result_receiver) = mpsc::channel();
+                        pool_scope.spawn(move |_| {
+                            let mut output = vec![];
+                            let result = search_entry(entry, false, &mut output);
+                            // The receiver is gone when printing has failed.
+                            let _ = result_sender.send(result.map(|search| (output, search)));
+                        });
+
+                        // Stop when printing has failed, rather than search the rest for nothing.
+                        if sender.send(result_receiver).is_err() {
+                            break;
+                        }
+                    }
+                });
+            });
+
+            for result_receiver in receiver {
+                let (output, search) = result_receiver.recv()??;
+
+                if has_context && totals.has_printed_group && search.has_printed_group {
+                    writeln!(writer, "--")?;
+                }
+                writer.write_all(&output)?;
+                totals.add(search, args.no_messages, &mut error_writer)?;
             }
-            Ok(filename) => {
-                // Attempt to open a file. This might fail due to permissions.
-                match open_input_file(&filename) {
-                    Err(e) => {
-                        eprintln!("{filename}: {e}")
+
+            Ok(())
+        })?;
+    }
+
+    writer.flush()?;
+
+    Ok(totals.outcome())
+}
+
+// Searches a single file and writes its output. When a group of an earlier file has been printed,
+// the first group of this one is separated from it.
+fn search_file(
+    filename: &str,
+    settings: &SearchSettings,
+    is_after_group: bool,
+    mut writer: impl Write,
+) -> anyhow::Result<FileSearch> {
+    let SearchSettings {
+        args,
+        pattern,
+        file_count,
+        use_color,
+        before_context,
+        after_context,
+    } = *settings;
+
+    let mut search = FileSearch::default();
+
+    // Handle the printing of the output with or without the filenames given the number of input
+    // files. Like GNU grep, the filename is followed by ":" on matching lines and by "-" on context
+    // lines, while a row without a filename such as the group separator is printed as it is.
+    let mut print_result_row =
+        |fname: Option<&str>, delimiter: char, text: &str| -> anyhow::Result<()> {
+            match fname {
+                Some(fname) if file_count > 1 => write!(
+                    writer,
+                    "{}{delimiter}{text}",
+                    paint(fname, FILENAME_COLOR, use_color)
+                )?,
+                _ => write!(writer, "{text}")?,
+            }
+
+            Ok(())
+        };
+
+    // Attempt to open a file. This might fail due to permissions.
+    match open_input(filename) {
+        Err(e) => search.errors.push(e.to_string()),
+        Ok(filehandle) => {
+            // Lazily find the matching lines of text.
+            let matching_lines = find_lines(
+                filehandle,
+                pattern,
+                args.invert_match,
+                before_context,
+                after_context,
+            );
+
+            // Decide whether to print the number of matches or the matches
+            // themselves.
+            if args.count {
+                // Count the matching lines without keeping them around. A read
+                // error discards the partial count.
+                match matching_lines
+                    .map(|line| line.map(|_| 1))
+                    .sum::<anyhow::Result<usize>>()
+                {
+                    Err(e) => search.errors.push(format!("{filename}: {e}")),
+                    Ok(count) => {
+                        // The exit code depends on whether any line was counted,
+                        // not on the counts printed.
+                        search.has_matched |= count > 0;
+                        print_result_row(Some(filename), ':', &format!("{count}\n"))?
                     }
-                    Ok(filehandle) => {
-                        // Attempt to find the matching lines of text.
-                        match find_lines(filehandle, &pattern, args.invert_match) {
-                            Err(e) => {
-                                eprintln!("{e}")
-                            }
-                            Ok(matching_lines) => {
-                                // Decide whether to print the number of matches or the matches
-                                // themselves.
-                                if args.count {
-                                    // Print the number of matching lines.
-                                    print_result_row(
-                                        &filename,
-                                        &format!("{}\n", matching_lines.len()),
-                                    );
-                                } else {
-                                    // Print the matching lines themselves.
-                                    for matching_line in &matching_lines {
-                                        print_result_row(&filename, matching_line);
-                                    }
-                                }
+                }
+            } else {
+                // Print the matching lines themselves as soon as they are found.
+                for found_line in matching_lines {
+                    let (delimiter, line_number, line_offset, matching_line) = match found_line {
+                        Err(e) => {
+                            search.errors.push(format!("{filename}: {e}"));
+                            break;
+                        }
+                        Ok(FoundLine::Match(line_number, line_offset, text)) => {
+                            search.has_matched = true;
+                            (':', line_number, line_offset, text)
+                        }
+                        Ok(FoundLine::Context(line_number, line_offset, text)) => {
+                            ('-', line_number, line_offset, text)
+                        }
+                        Ok(FoundLine::Separator) => {
+                            print_result_row(None, ':', "--\n")?;
+                            continue;
+                        }
+                    };
+
+                    if is_after_group && !search.has_printed_group {
+                        print_result_row(None, ':', "--\n")?;
+                    }
+                    search.has_printed_group = true;
+
+                    // With --only-matching, print each match on its own line at its
+                    // own offset instead of the whole line. The context lines have no
+                    // match to print, and neither do the lines selected by -v.
+                    let texts: Vec<(usize, Cow<str>)> = if args.only_matching {
+                        if delimiter != ':' {
+                            continue;
+                        }
+
+                        // Like GNU grep, an empty match prints nothing.
+                        pattern
+                            .find_iter(&matching_line)
+                            .filter(|found| !found.is_empty())
+                            .map(|found| {
+                                (
+                                    line_offset + found.start(),
+                                    Cow::Owned(format!("{}\n", found.as_str())),
+                                )
+                            })
+                            .collect()
+                    } else {
+                        vec![(line_offset, Cow::Borrowed(matching_line.as_str()))]
+                    };
+
+                    for (byte_offset, text) in texts {
+                        // Shorten very long lines such as minified code so that they
+                        // do not flood the terminal. The matches are colored before the
+                        // line is cut.
+                        let highlight_pattern = use_color.then_some(pattern);
+                        let text = match (args.max_columns, highlight_pattern) {
+                            (Some(max_columns), _) => Cow::Owned(
+                                truncate_line(&text, max_columns, highlight_pattern).into_owned(),
+                            ),
+                            (None, Some(pattern)) => {
+                                Cow::Owned(highlight_matches(&text, pattern).into_owned())
                             }
+                            (None, None) => text,
+                        };
+
+                        // Like GNU grep, the line number comes before the byte offset.
+                        let mut prefix = String::new();
+                        if args.line_number {
+                            let line_number = line_number.to_string();
+                            prefix.push_str(&format!(
+                                "{}{delimiter}",
+                                paint(&line_number, LINE_NUMBER_COLOR, use_color)
+                            ));
                         }
+                        if args.byte_offset {
+                            let byte_offset = byte_offset.to_string();
+                            prefix.push_str(&format!(
+                                "{}{delimiter}",
+                                paint(&byte_offset, LINE_NUMBER_COLOR, use_color)
+                            ));
+                        }
+
+                        print_result_row(Some(filename), delimiter, &format!("{prefix}{text}"))?
                     }
                 }
             }
         }
     }
 
-    Ok(())
+    Ok(search)
 }
 
-// Opening user-provided input source
+// Reads one pattern per line from a file, skipping the blank lines.
+fn read_pattern_file(filename: &str) -> anyhow::Result<Vec<String>> {
+    let filehandle = open_input(filename)?;
+
+    let mut patterns = vec![];
+
+    for line in filehandle.lines() {
+        let line = line.map_err(|e| anyhow::anyhow!("{filename}: {e}"))?;
+        let pattern = line.trim_end_matches('\r');
 
-fn open_input_file(filename: &str) -> anyhow::Result<Box<dyn BufRead>> {
-    match filename {
-        "-" => Ok(Box::new(BufReader::new(io::stdin()))),
-        path => Ok(Box::new(BufReader::new(File::open(path)?))),
+        if !pattern.is_empty() {
+            patterns.push(pattern.to_string());
+        }
     }
+
+    Ok(patterns)
 }
 
-fn find_files(paths: &[String], recursive: bool) -> Vec<anyhow::Result<String>> {
+fn find_files(
+    paths: &[String],
+    recursive: bool,
+    includes: &[globset::GlobMatcher],
+    excludes: &[globset::GlobMatcher],
+) -> Vec<anyhow::Result<String>> {
     // Initialize an empty vector to hold the results.
     let mut results = vec![];
 
@@ -146,7 +638,10 @@ fn find_files(paths: &[String], recursive: bool) -> Vec<anyhow::Result<String>>
                         if metadata.is_dir() {
                             if recursive {
                                 // Add to the results all the files in the given directory.
+                                // Sort the entries so that the output does not depend on
+                                // the order of the file system.
                                 for entry in WalkDir::new(path)
+                                    .sort_by_file_name()
                                     .into_iter()
                                     // Iterator::flatten will take the Ok or Some variants for
                                     // Result and Option types and will ignore Err and None
@@ -154,6 +649,14 @@ fn find_files(paths: &[String], recursive: bool) -> Vec<anyhow::Result<String>>
                                     // found by recursing through directories.
                                     .flatten()
                                     .filter(|e| e.file_type().is_file())
+                                    // Search a file only when its name matches at least one
+                                    // include, if any, and none of the excludes.
+                                    .filter(|e| {
+                                        let file_name = e.file_name();
+                                        (includes.is_empty()
+                                            || includes.iter().any(|glob| glob.is_match(file_name)))
+                                            && !excludes.iter().any(|glob| glob.is_match(file_name))
+                                    })
                                 {
                                     results.push(Ok(entry.path().display().to_string()));
                                 }
@@ -177,59 +680,268 @@ fn find_files(paths: &[String], recursive: bool) -> Vec<anyhow::Result<String>>
     results
 }
 
-fn find_lines(
-    mut filehandle: impl BufRead,
-    pattern: &Regex,
+/// Represents a line found by `find_lines`, or the separator between two groups of lines
+#[derive(Debug, PartialEq)]
+enum FoundLine {
+    /// A selected line with its 1-based line number and the 0-based byte offset of its start
+    Match(usize, usize, String),
+    /// A line of context around a selected line with its line number and byte offset
+    Context(usize, usize, String),
+    /// The boundary between two groups of lines that are not contiguous
+    Separator,
+}
+
+/// Returns an iterator over the lines of the filehandle that match the pattern, or the lines that
+/// do not match when `invert_match` is true. Each line keeps its original line ending.
+///
+/// Up to `before_context` and `after_context` lines around each selected line are yielded as
+/// context. Overlapping context is merged, and a separator is yielded between groups that are not
+/// contiguous, like GNU grep. Without context, only the selected lines are yielded.
+///
+/// The lines are read one at a time as the iterator is advanced, so only the current line and the
+/// lines of leading context are held in memory no matter how large the file is. Invalid UTF-8 is
+/// decoded lossily, line by line, rather than ending the search.
+fn find_lines<'a>(
+    mut filehandle: impl BufRead + 'a,
+    pattern: &'a Regex,
     invert_match: bool,
-) -> anyhow::Result<Vec<String>> {
-    let mut matches = vec![];
+    before_context: usize,
+    after_context: usize,
+) -> impl Iterator<Item = anyhow::Result<FoundLine>> + 'a {
+    // Read the raw bytes of each line, since a file may contain bytes that are not UTF-8.
+    let mut buffer: Vec<u8> = vec![];
     let mut line = String::new();
+    // Count every line that is read, not only the matching ones.
+    let mut line_number = 0;
+    // Count the bytes before the current line, which restarts from zero for every file.
+    let mut byte_offset = 0;
+    // The rolling buffer of the most recent lines that may become leading context.
+    let mut before_lines: VecDeque<(usize, usize, String)> =
+        VecDeque::with_capacity(before_context);
+    // The number of lines still to be yielded as trailing context.
+    let mut after_remaining = 0;
+    // The number of the last line yielded, to decide where a separator is needed.
+    let mut last_line_number: Option<usize> = None;
+    // A match may yield its leading context along with itself, so queue them up.
+    let mut found_lines: VecDeque<FoundLine> = VecDeque::new();
 
-    loop {
-        let bytes = filehandle.read_line(&mut line)?;
-
-        if bytes == 0 {
-            break;
+    std::iter::from_fn(move || loop {
+        if let Some(found_line) = found_lines.pop_front() {
+            return Some(Ok(found_line));
         }
 
-        // The bitwise XOR comparison (^) determines if the line should be included.
-        if pattern.is_match(&line) ^ invert_match {
-            // Use std::mem::take to take ownership of the line.
-            // Alternatively, we sould clone to copy the string.
-            matches.push(mem::take(&mut line));
+        buffer.clear();
+
+        // BufRead::read_line would fail on the first invalid byte and end the search of the whole
+        // file, so read up to the newline instead.
+        match filehandle.read_until(b'\n', &mut buffer) {
+            Err(e) => return Some(Err(e.into())),
+            // Stop at the end of the file.
+            Ok(0) => return None,
+            Ok(bytes_read) => {
+                line_number += 1;
+                let line_offset = byte_offset;
+                byte_offset += bytes_read;
+
+                // Replace the invalid bytes with U+FFFD so that the line can still be matched and
+                // printed. The offsets of -b keep counting the raw bytes.
+                line.clear();
+                match String::from_utf8_lossy(&buffer) {
+                    Cow::Borrowed(text) => line.push_str(text),
+                    Cow::Owned(text) => line = text,
+                }
+
+                // The bitwise XOR comparison (^) determines if the line should be included.
+                if pattern.is_match(&line) ^ invert_match {
+                    // Separate this group from the previous one when there is a gap between them.
+                    let first_line_number =
+                        before_lines.front().map_or(line_number, |(n, _, _)| *n);
+                    let has_context = before_context > 0 || after_context > 0;
+                    if has_context && last_line_number.is_some_and(|n| n + 1 < first_line_number) {
+                        found_lines.push_back(FoundLine::Separator);
+                    }
+
+                    found_lines.extend(
+                        before_lines
+                            .drain(..)
+                            .map(|(n, offset, text)| FoundLine::Context(n, offset, text)),
+                    );
+                    // Use std::mem::take to take ownership of the line.
+                    // Alternatively, we sould clone to copy the string.
+                    found_lines.push_back(FoundLine::Match(
+                        line_number,
+                        line_offset,
+                        mem::take(&mut line),
+                    ));
+
+                    after_remaining = after_context;
+                    last_line_number = Some(line_number);
+                } else if after_remaining > 0 {
+                    found_lines.push_back(FoundLine::Context(
+                        line_number,
+                        line_offset,
+                        mem::take(&mut line),
+                    ));
+
+                    after_remaining -= 1;
+                    last_line_number = Some(line_number);
+                } else if before_context > 0 {
+                    // Keep only the last lines that fit in the leading context.
+                    if before_lines.len() == before_context {
+                        before_lines.pop_front();
+                    }
+
+                    before_lines.push_back((line_number, line_offset, mem::take(&mut line)));
+                }
+            }
         }
+    })
+}
 
-        line.clear();
+/// Compiles a shell-style glob pattern for matching file names.
+fn parse_glob(pattern: &str) -> Result<globset::GlobMatcher, globset::Error> {
+    Ok(globset::Glob::new(pattern)?.compile_matcher())
+}
+
+/// Wraps the text in the escape sequences of the color when coloring is on.
+fn paint<'a>(text: &'a str, color: &str, use_color: bool) -> Cow<'a, str> {
+    if use_color {
+        Cow::Owned(format!("{color}{text}{RESET_COLOR}"))
+    } else {
+        Cow::Borrowed(text)
     }
+}
 
-    Ok(matches)
+/// Colors every non-empty match of the pattern within the line, leaving the rest of the line and
+/// its line ending as they are.
+fn highlight_matches<'a>(line: &'a str, pattern: &Regex) -> Cow<'a, str> {
+    highlight_matches_before(line, line.len(), pattern)
+}
+
+/// Colors the non-empty matches of the pattern in the whole line, but returns only the line
+/// before the byte index `end`. A match that runs past `end` is colored up to it.
+fn highlight_matches_before<'a>(line: &'a str, end: usize, pattern: &Regex) -> Cow<'a, str> {
+    let mut highlighted = String::new();
+    let mut last_end = 0;
+
+    for found in pattern
+        .find_iter(line)
+        .filter(|found| !found.is_empty())
+        .take_while(|found| found.start() < end)
+    {
+        let found_end = found.end().min(end);
+        highlighted.push_str(&line[last_end..found.start()]);
+        highlighted.push_str(&paint(&line[found.start()..found_end], MATCH_COLOR, true));
+        last_end = found_end;
+    }
+
+    if last_end == 0 {
+        return Cow::Borrowed(&line[..end]);
+    }
+
+    highlighted.push_str(&line[last_end..end]);
+    Cow::Owned(highlighted)
+}
+
+/// Truncates a line to its first `max_columns` characters, replacing the rest with a marker that
+/// tells how many bytes were omitted. The line ending is kept. With a pattern to highlight, the
+/// matches are found in the whole line before it is cut, and the marker is never colored.
+fn truncate_line<'a>(
+    line: &'a str,
+    max_columns: usize,
+    highlight_pattern: Option<&Regex>,
+) -> Cow<'a, str> {
+    // Leave the line ending out of the count so that it is still printed.
+    let content = line.trim_end_matches(['\r', '\n']);
+    let line_ending = &line[content.len()..];
+
+    let Some((cut_index, _)) = content.char_indices().nth(max_columns) else {
+        // The line is short enough.
+        return match highlight_pattern {
+            Some(pattern) => highlight_matches(line, pattern),
+            None => Cow::Borrowed(line),
+        };
+    };
+
+    let kept = match highlight_pattern {
+        Some(pattern) => highlight_matches_before(line, cut_index, pattern),
+        None => Cow::Borrowed(&content[..cut_index]),
+    };
+    Cow::Owned(format!(
+        "{kept}[... {} bytes omitted]{line_ending}",
+        content.len() - cut_index
+    ))
 }
 
 // Unit testing
 
 #[cfg(test)]
 mod tests {
-    use super::{find_files, find_lines};
+    use super::{
+        do_run, find_files, find_lines, highlight_matches, read_pattern_stdin, truncate_line,
+        CliArguments, FoundLine, SearchOutcome,
+    };
+    use clap::Parser;
     use rand::{distributions::Alphanumeric, Rng};
     use regex::{Regex, RegexBuilder};
-    use std::io::Cursor;
+    use std::{
+        fs,
+        io::{self, Cursor, IsTerminal},
+        path::Path,
+    };
+
+    // What a run of grepr wrote, and how its search turned out
+    #[derive(Debug, PartialEq)]
+    struct GreprRun {
+        output: String,
+        error_output: String,
+        outcome: SearchOutcome,
+    }
+
+    // Runs grepr with the arguments after the program name, feeding STDIN to --pattern-stdin.
+    // An error such as an invalid pattern is returned as it is.
+    fn run_grepr_stdin(flags: &[&str], stdin: &str) -> anyhow::Result<GreprRun> {
+        let argv = std::iter::once("grepr").chain(flags.iter().copied());
+        let args = read_pattern_stdin(CliArguments::parse_from(argv), Cursor::new(stdin))?;
+
+        let mut output = vec![];
+        let mut error_output = vec![];
+        let outcome = do_run(args, &mut output, &mut error_output)?;
+        Ok(GreprRun {
+            output: String::from_utf8(output).unwrap(),
+            error_output: String::from_utf8(error_output).unwrap(),
+            outcome,
+        })
+    }
+
+    // Runs grepr with the arguments after the program name and an empty STDIN.
+    fn run_grepr(flags: &[&str]) -> anyhow::Result<GreprRun> {
+        run_grepr_stdin(flags, "")
+    }
+
+    // Writes a file into the directory, and returns its path.
+    fn write_file(dir: &Path, name: &str, contents: impl AsRef<[u8]>) -> String {
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        path.display().to_string()
+    }
 
     #[test]
     fn test_find_files() {
         // Verify that the function finds a file known to exist
-        let files = find_files(&["./tests/inputs/fox.txt".to_string()], false);
+        let files = find_files(&["./tests/inputs/fox.txt".to_string()], false, &[], &[]);
         assert_eq!(files.len(), 1);
         assert_eq!(files[0].as_ref().unwrap(), "./tests/inputs/fox.txt");
 
         // The function should reject a directory without the recursive option
-        let files = find_files(&["./tests/inputs".to_string()], false);
+        let files = find_files(&["./tests/inputs".to_string()], false, &[], &[]);
         assert_eq!(files.len(), 1);
         if let Err(e) = &files[0] {
             assert_eq!(e.to_string(), "./tests/inputs is a directory");
         }
 
         // Verify that the function recurses to find four files in the directory
-        let res = find_files(&["./tests/inputs".to_string()], true);
+        let res = find_files(&["./tests/inputs".to_string()], true, &[], &[]);
         let mut files: Vec<String> = res
             .iter()
             .map(|r| r.as_ref().unwrap().replace("\\", "/"))
@@ -254,23 +966,59 @@ mod tests {
             .collect();
 
         // Verify that the function returns the bad file as an error
-        let files = find_files(&[bad], false);
+        let files = find_files(&[bad], false, &[], &[]);
         assert_eq!(files.len(), 1);
         assert!(files[0].is_err());
     }
 
+    #[test]
+    fn test_find_files_include_exclude() {
+        let find = |flags: &[&str]| -> Vec<String> {
+            let mut argv = vec!["grepr", "-r"];
+            argv.extend_from_slice(flags);
+            argv.extend(["pattern", "./tests/inputs"]);
+            let args = CliArguments::parse_from(argv);
+
+            let mut files: Vec<String> =
+                find_files(&args.files, args.recursive, &args.includes, &args.excludes)
+                    .into_iter()
+                    .map(|r| r.unwrap().replace('\\', "/"))
+                    .collect();
+            files.sort();
+            files
+        };
+
+        assert_eq!(find(&["--include", "*.txt"]).len(), 4);
+        assert_eq!(
+            find(&["--include", "f*.txt", "--include", "n*"]),
+            ["./tests/inputs/fox.txt", "./tests/inputs/nobody.txt"]
+        );
+
+        // An exclude wins over an include
+        assert_eq!(
+            find(&["--include", "*.txt", "--exclude", "[bfn]*"]),
+            ["./tests/inputs/empty.txt"]
+        );
+        assert!(find(&["--include", "*.rs"]).is_empty());
+
+        // Invalid globs are rejected
+        assert!(CliArguments::try_parse_from(["grepr", "--include", "[a", "pattern"]).is_err());
+    }
+
     #[test]
     fn test_find_lines() {
         let text = b"Lorem\nIpsum\r\nDOLOR";
 
         // The pattern "or" should match the one line "Lorem"
         let re1 = Regex::new("or").unwrap();
-        let matches = find_lines(Cursor::new(&text), &re1, false);
+        let matches: anyhow::Result<Vec<_>> =
+            find_lines(Cursor::new(&text), &re1, false, 0, 0).collect();
         assert!(matches.is_ok());
         assert_eq!(matches.unwrap().len(), 1);
 
         // When interted, the function should match the other two lines
-        let matches = find_lines(Cursor::new(&text), &re1, true);
+        let matches: anyhow::Result<Vec<_>> =
+            find_lines(Cursor::new(&text), &re1, true, 0, 0).collect();
         assert!(matches.is_ok());
         assert_eq!(matches.unwrap().len(), 2);
 
@@ -281,13 +1029,690 @@ mod tests {
             .unwrap();
 
         // The two lines "Lorem" and "DOLOR" should match
-        let matches = find_lines(Cursor::new(&text), &re2, false);
+        let matches: anyhow::Result<Vec<_>> =
+            find_lines(Cursor::new(&text), &re2, false, 0, 0).collect();
         assert!(matches.is_ok());
         assert_eq!(matches.unwrap().len(), 2);
 
         // When inverted, the one remaining line should match
-        let matches = find_lines(Cursor::new(&text), &re2, true);
+        let matches: anyhow::Result<Vec<_>> =
+            find_lines(Cursor::new(&text), &re2, true, 0, 0).collect();
         assert!(matches.is_ok());
         assert_eq!(matches.unwrap().len(), 1);
     }
+
+    #[test]
+    fn test_find_lines_word_regexp() {
+        let text = b"or\nword\nthis or that\nOR\n";
+
+        // The word-bounded pattern skips "or" inside "word"
+        let re = Regex::new(r"\b(?:or)\b").unwrap();
+        let matches: anyhow::Result<Vec<_>> =
+            find_lines(Cursor::new(&text), &re, false, 0, 0).collect();
+        assert_eq!(
+            matches.unwrap(),
+            [
+                FoundLine::Match(1, 0, "or\n".to_string()),
+                FoundLine::Match(3, 8, "this or that\n".to_string())
+            ]
+        );
+
+        // The same transformation is applied by do_run, keeping case-insensitivity
+        let dir = tempfile::tempdir().unwrap();
+        let filename = write_file(dir.path(), "words.txt", text);
+
+        assert_eq!(
+            run_grepr(&["-w", "-i", "or|that", &filename])
+                .unwrap()
+                .output,
+            "or\nthis or that\nOR\n"
+        );
+
+        // A bad pattern is still reported as the user wrote it
+        assert_eq!(
+            run_grepr(&["-w", "*", &filename]).unwrap_err().to_string(),
+            r#"Invalid pattern "*""#
+        );
+    }
+
+    #[test]
+    fn test_no_messages() {
+        // Search a readable file along with a directory, which cannot be searched without -r,
+        // and a file that does not exist. Both fail the same way for any user, even root.
+        let dir = tempfile::tempdir().unwrap();
+        let filename = write_file(dir.path(), "fox.txt", "The quick brown fox\n");
+        let dirname = dir.path().display().to_string();
+        let inputs = ["fox", &filename, &dirname, "no-such-file"];
+
+        // By default, the directory and the nonexistent file are reported
+        let run = run_grepr(&inputs).unwrap();
+        assert!(run.error_output.contains("is a directory"));
+        assert!(run.error_output.contains("no-such-file"));
+        assert_eq!(run.outcome, SearchOutcome::Failed);
+
+        // With --no-messages, nothing is written to STDERR, but the errors still fail the search
+        for flag in ["-s", "--no-messages"] {
+            let run = run_grepr(&[&[flag][..], &inputs].concat()).unwrap();
+            assert_eq!(run.error_output, "");
+            assert_eq!(run.outcome, SearchOutcome::Failed);
+        }
+    }
+
+    #[test]
+    fn test_do_run_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let filename = write_file(dir.path(), "lorem.txt", "Lorem\nIpsum\r\nDOLOR\n");
+
+        // The matching lines are written with their original line endings
+        assert_eq!(
+            run_grepr(&["-i", "or", &filename]).unwrap().output,
+            "Lorem\nDOLOR\n"
+        );
+        assert_eq!(
+            run_grepr(&["-v", "or", &filename]).unwrap().output,
+            "Ipsum\r\nDOLOR\n"
+        );
+
+        // The count is written instead of the lines
+        assert_eq!(run_grepr(&["-c", "or", &filename]).unwrap().output, "1\n");
+    }
+
+    #[test]
+    fn test_truncate_line() {
+        // Short lines are left alone
+        assert_eq!(truncate_line("short\n", 20, None), "short\n");
+        assert_eq!(truncate_line("exactly\n", 7, None), "exactly\n");
+
+        // Long lines keep their first characters and their line ending
+        assert_eq!(
+            truncate_line("abcdefghij\r\n", 4, None),
+            "abcd[... 6 bytes omitted]\r\n"
+        );
+        assert_eq!(
+            truncate_line("abcdefghij", 4, None),
+            "abcd[... 6 bytes omitted]"
+        );
+
+        // The cut never splits a multibyte character
+        assert_eq!(
+            truncate_line("日本語です\n", 2, None),
+            "日本[... 9 bytes omitted]\n"
+        );
+
+        // A match cut by the truncation is still colored up to the cut, while the marker is not
+        // colored even when the pattern matches it
+        let re = Regex::new("cdef|bytes").unwrap();
+        assert_eq!(
+            truncate_line("abcdefghij bytes\n", 4, Some(&re)),
+            "ab\x1b[01;31mcd\x1b[m[... 12 bytes omitted]\n"
+        );
+        assert_eq!(
+            truncate_line("abc bytes\n", 20, Some(&re)),
+            "abc \x1b[01;31mbytes\x1b[m\n"
+        );
+    }
+
+    #[test]
+    fn test_max_columns() {
+        let dir = tempfile::tempdir().unwrap();
+        let long_line = format!("var fox=1;{}\n", "x".repeat(100));
+        let filename = write_file(
+            dir.path(),
+            "minified.js",
+            format!("short fox\n{long_line}no match\n"),
+        );
+
+        assert_eq!(
+            run_grepr(&["--max-columns", "20", "fox", &filename])
+                .unwrap()
+                .output,
+            "short fox\nvar fox=1;xxxxxxxxxx[... 90 bytes omitted]\n"
+        );
+    }
+
+    #[test]
+    fn test_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let fox = write_file(dir.path(), "fox.txt", "The quick brown fox\njumps over\n");
+        write_file(dir.path(), "empty.txt", "");
+        write_file(dir.path(), "nobody.txt", "The\nthe\nTHE\n");
+        let dirname = dir.path().display().to_string();
+
+        let run = |flags: &[&str]| -> Vec<String> {
+            // Sort the rows because the directory walk order is not defined.
+            let mut rows: Vec<String> = run_grepr(flags)
+                .unwrap()
+                .output
+                .lines()
+                .map(|row| row.replace(&dirname, "DIR").replace('\\', "/"))
+                .collect();
+            rows.sort();
+            rows
+        };
+
+        // Each file gets a filename:COUNT row when there are several files
+        assert_eq!(
+            run(&["-r", "-c", "The", &dirname]),
+            ["DIR/empty.txt:0", "DIR/fox.txt:1", "DIR/nobody.txt:1"]
+        );
+        assert_eq!(
+            run(&["-r", "-c", "-i", "The", &dirname]),
+            ["DIR/empty.txt:0", "DIR/fox.txt:1", "DIR/nobody.txt:3"]
+        );
+
+        // With -v, the count reflects the non-matching lines
+        assert_eq!(
+            run(&["-r", "-c", "-v", "The", &dirname]),
+            ["DIR/empty.txt:0", "DIR/fox.txt:1", "DIR/nobody.txt:2"]
+        );
+
+        // A single file gets only the COUNT
+        assert_eq!(run(&["--count", "o", &fox]), ["2"]);
+    }
+
+    #[test]
+    fn test_find_lines_line_numbers() {
+        let text = b"Lorem\nIpsum\r\nDOLOR\nlorem";
+        let re = RegexBuilder::new("lorem")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+
+        // The numbers count the non-matching lines in between
+        let matches: anyhow::Result<Vec<_>> =
+            find_lines(Cursor::new(&text), &re, false, 0, 0).collect();
+        assert_eq!(
+            matches.unwrap(),
+            [
+                FoundLine::Match(1, 0, "Lorem\n".to_string()),
+                FoundLine::Match(4, 19, "lorem".to_string())
+            ]
+        );
+
+        // Inverted matches keep their own line numbers
+        let matches: anyhow::Result<Vec<_>> =
+            find_lines(Cursor::new(&text), &re, true, 0, 0).collect();
+        assert_eq!(
+            matches.unwrap(),
+            [
+                FoundLine::Match(2, 6, "Ipsum\r\n".to_string()),
+                FoundLine::Match(3, 13, "DOLOR\n".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_line_number_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let filename = write_file(dir.path(), "lorem.txt", "Lorem\nIpsum\nlorem\n");
+
+        assert_eq!(
+            run_grepr(&["-n", "-i", "lorem", &filename]).unwrap().output,
+            "1:Lorem\n3:lorem\n"
+        );
+
+        // With several files, the filename comes before the line number
+        assert_eq!(
+            run_grepr(&["--line-number", "Ipsum", &filename, &filename])
+                .unwrap()
+                .output,
+            format!("{filename}:2:Ipsum\n{filename}:2:Ipsum\n")
+        );
+    }
+
+    #[test]
+    fn test_pattern_stdin() {
+        let dir = tempfile::tempdir().unwrap();
+        let fox = write_file(dir.path(), "fox.txt", "The quick brown fox\n");
+        let dog = write_file(dir.path(), "dog.txt", "jumps over the lazy dog\n");
+
+        // Every positional argument is a file, including the first one
+        assert_eq!(
+            run_grepr_stdin(&["--pattern-stdin", &fox, &dog], "the\n")
+                .unwrap()
+                .output,
+            format!("{dog}:jumps over the lazy dog\n")
+        );
+        assert_eq!(
+            run_grepr_stdin(&["--pattern-stdin", "-i", &fox], "THE\r\n")
+                .unwrap()
+                .output,
+            "The quick brown fox\n"
+        );
+
+        // STDIN cannot hold both the pattern and the input
+        assert!(run_grepr_stdin(&["--pattern-stdin"], "the\n").is_err());
+        assert!(run_grepr_stdin(&["--pattern-stdin", &fox, "-"], "the\n").is_err());
+
+        // An empty STDIN has no pattern, rather than one that matches every line
+        let error = run_grepr_stdin(&["--pattern-stdin", &fox], "").unwrap_err();
+        assert_eq!(error.to_string(), "--pattern-stdin: no pattern on STDIN");
+
+        // Without the flag, the first positional argument is still the pattern
+        assert_eq!(
+            run_grepr(&["fox", &fox, &dog]).unwrap().output,
+            format!("{fox}:The quick brown fox\n")
+        );
+    }
+
+    #[test]
+    fn test_find_lines_context() {
+        let text = b"one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\n";
+        let context = |pattern: &str, before: usize, after: usize| -> Vec<FoundLine> {
+            let re = Regex::new(pattern).unwrap();
+            find_lines(Cursor::new(&text), &re, false, before, after)
+                .collect::<anyhow::Result<_>>()
+                .unwrap()
+        };
+
+        // A single match with one line of context on each side
+        assert_eq!(
+            context("four", 1, 1),
+            [
+                FoundLine::Context(3, 8, "three\n".to_string()),
+                FoundLine::Match(4, 14, "four\n".to_string()),
+                FoundLine::Context(5, 19, "five\n".to_string()),
+            ]
+        );
+
+        // The context stops at the start and the end of the file
+        assert_eq!(
+            context("one|eight", 2, 2),
+            [
+                FoundLine::Match(1, 0, "one\n".to_string()),
+                FoundLine::Context(2, 4, "two\n".to_string()),
+                FoundLine::Context(3, 8, "three\n".to_string()),
+                FoundLine::Separator,
+                FoundLine::Context(6, 24, "six\n".to_string()),
+                FoundLine::Context(7, 28, "seven\n".to_string()),
+                FoundLine::Match(8, 34, "eight\n".to_string()),
+            ]
+        );
+
+        // Overlapping windows are merged without a separator or duplicate lines
+        assert_eq!(
+            context("two|five", 1, 2),
+            [
+                FoundLine::Context(1, 0, "one\n".to_string()),
+                FoundLine::Match(2, 4, "two\n".to_string()),
+                FoundLine::Context(3, 8, "three\n".to_string()),
+                FoundLine::Context(4, 14, "four\n".to_string()),
+                FoundLine::Match(5, 19, "five\n".to_string()),
+                FoundLine::Context(6, 24, "six\n".to_string()),
+                FoundLine::Context(7, 28, "seven\n".to_string()),
+            ]
+        );
+
+        // Adjacent groups need no separator
+        assert_eq!(
+            context("^(two|five)$", 0, 2)
+                .iter()
+                .filter(|found_line| **found_line == FoundLine::Separator)
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_context_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let filename = write_file(
+            dir.path(),
+            "numbers.txt",
+            "one\ntwo\nthree\nfour\nfive\nsix\n",
+        );
+
+        assert_eq!(
+            run_grepr(&["-A1", "-B1", "three", &filename])
+                .unwrap()
+                .output,
+            "two\nthree\nfour\n"
+        );
+
+        // Context lines are marked with "-" instead of ":"
+        assert_eq!(
+            run_grepr(&["-n", "-C1", "one|six", &filename])
+                .unwrap()
+                .output,
+            "1:one\n2-two\n--\n5-five\n6:six\n"
+        );
+        assert_eq!(
+            run_grepr(&["-B1", "two", &filename, &filename])
+                .unwrap()
+                .output,
+            format!("{filename}-one\n{filename}:two\n--\n{filename}-one\n{filename}:two\n")
+        );
+
+        // -A overrides -C, and the count ignores the context
+        assert_eq!(
+            run_grepr(&["-C2", "-A0", "four", &filename])
+                .unwrap()
+                .output,
+            "two\nthree\nfour\n"
+        );
+        assert_eq!(
+            run_grepr(&["-c", "-C2", "four", &filename]).unwrap().output,
+            "1\n"
+        );
+    }
+
+    #[test]
+    fn test_regexps() {
+        let dir = tempfile::tempdir().unwrap();
+        let filename = write_file(dir.path(), "animals.txt", "fox\ndog\ncat\nfoxhound\n");
+
+        // A line matches when any of the patterns does
+        assert_eq!(
+            run_grepr(&["-e", "fox", "-e", "cat", &filename])
+                .unwrap()
+                .output,
+            "fox\ncat\nfoxhound\n"
+        );
+
+        // Each pattern keeps its own anchors and alternations apart from the others
+        assert_eq!(
+            run_grepr(&["-e", "^fox$|x", "--regexp", "^d", &filename])
+                .unwrap()
+                .output,
+            "fox\ndog\nfoxhound\n"
+        );
+        assert_eq!(
+            run_grepr(&["-w", "-e", "fox", "-e", "DOG", "-i", &filename])
+                .unwrap()
+                .output,
+            "fox\ndog\n"
+        );
+
+        // With -e, every positional argument is a file
+        assert_eq!(
+            run_grepr(&["-e", "cat", &filename, &filename])
+                .unwrap()
+                .output,
+            format!("{filename}:cat\n{filename}:cat\n")
+        );
+
+        // The invalid pattern is named in the error
+        assert_eq!(
+            run_grepr(&["-e", "fox", "-e", "*", &filename])
+                .unwrap_err()
+                .to_string(),
+            r#"Invalid pattern "*""#
+        );
+    }
+
+    #[test]
+    fn test_pattern_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let filename = write_file(dir.path(), "animals.txt", "fox\ndog\ncat\nowl\n");
+        let patterns = write_file(dir.path(), "patterns.txt", "fox\n\n^c\r\n");
+
+        // The blank line does not match every line
+        assert_eq!(
+            run_grepr(&["-f", &patterns, "-e", "fox", &filename])
+                .unwrap()
+                .output,
+            "fox\ncat\n"
+        );
+
+        // The positional pattern and the -e patterns are combined with the file patterns
+        assert_eq!(
+            run_grepr(&["--file", &patterns, "owl", &filename])
+                .unwrap()
+                .output,
+            "fox\ncat\nowl\n"
+        );
+        assert_eq!(
+            run_grepr(&["-f", &patterns, "-e", "dog", &filename])
+                .unwrap()
+                .output,
+            "fox\ndog\ncat\n"
+        );
+
+        // The missing patterns file is named in the error
+        let missing = dir.path().join("missing.txt").display().to_string();
+        let error = run_grepr(&["-f", &missing, "fox", &filename]).unwrap_err();
+        assert!(error.to_string().starts_with(&format!("{missing}: ")));
+    }
+
+    #[test]
+    fn test_fixed_strings() {
+        let dir = tempfile::tempdir().unwrap();
+        let filename = write_file(dir.path(), "dots.txt", "a.c\nabc\nA.C\n(x)*\n");
+
+        // The dot is a literal dot, not any character
+        assert_eq!(
+            run_grepr(&["-F", "a.c", &filename]).unwrap().output,
+            "a.c\n"
+        );
+        assert_eq!(run_grepr(&["a.c", &filename]).unwrap().output, "a.c\nabc\n");
+
+        // Case-insensitivity is kept, and every -e pattern is escaped
+        assert_eq!(
+            run_grepr(&["--fixed-strings", "-i", "a.c", &filename])
+                .unwrap()
+                .output,
+            "a.c\nA.C\n"
+        );
+        assert_eq!(
+            run_grepr(&["-F", "-e", "(x)*", "-e", "b", &filename])
+                .unwrap()
+                .output,
+            "abc\n(x)*\n"
+        );
+    }
+
+    #[test]
+    fn test_byte_offset_only_matching() {
+        let dir = tempfile::tempdir().unwrap();
+        let filename = write_file(dir.path(), "a.txt", "one fox\ntwo fox fox\n");
+        write_file(dir.path(), "b.txt", "fox\n");
+        let dirname = dir.path().display().to_string();
+
+        let run = |flags: &[&str]| -> String {
+            let mut rows: Vec<_> = run_grepr(flags)
+                .unwrap()
+                .output
+                .lines()
+                .map(|row| row.replace(&dirname, "DIR"))
+                .collect();
+            // The order of the files depends on the file system.
+            rows.sort();
+            rows.join("\n")
+        };
+
+        // The offsets of the lines restart from zero in each file
+        assert_eq!(
+            run(&["-b", "-r", "fox", &dirname]),
+            "DIR/a.txt:0:one fox\nDIR/a.txt:8:two fox fox\nDIR/b.txt:0:fox"
+        );
+
+        // With -o, each match is printed at its own offset
+        assert_eq!(
+            run(&["-b", "-o", "-r", "fox", &dirname]),
+            "DIR/a.txt:12:fox\nDIR/a.txt:16:fox\nDIR/a.txt:4:fox\nDIR/b.txt:0:fox"
+        );
+        assert_eq!(
+            run(&["-n", "-b", "-o", "-r", "tw.", &dirname]),
+            "DIR/a.txt:2:8:two"
+        );
+
+        // Without -b, only the matches are printed, and -v has no match to print
+        assert_eq!(run(&["-o", "f.x", &filename]), "fox\nfox\nfox");
+        assert_eq!(run(&["-o", "-v", "one", &filename]), "");
+
+        // A pattern that can match the empty string prints only its non-empty matches
+        assert_eq!(run(&["-o", "x*", &filename]), "x\nx\nx");
+    }
+
+    #[test]
+    fn test_search_outcome() {
+        let dir = tempfile::tempdir().unwrap();
+        let filename = write_file(dir.path(), "fox.txt", "The quick brown fox\n");
+        let run = |flags: &[&str]| run_grepr(flags).map(|run| run.outcome);
+
+        let outcome = run(&["fox", &filename]).unwrap();
+        assert_eq!(outcome, SearchOutcome::Matched);
+        assert_eq!(outcome.exit_code(), 0);
+
+        // An absent pattern is not an error, but it exits with 1
+        let outcome = run(&["dog", &filename]).unwrap();
+        assert_eq!(outcome, SearchOutcome::NotMatched);
+        assert_eq!(outcome.exit_code(), 1);
+
+        // A missing file fails even when other files match, and even with -s
+        for flags in [&[][..], &["-s"]] {
+            let outcome = run(&[&["fox", &filename, "no-such-file"], flags].concat()).unwrap();
+            assert_eq!(outcome, SearchOutcome::Failed);
+            assert_eq!(outcome.exit_code(), 2);
+        }
+
+        // A bad pattern is an error returned to main, which exits with 2
+        assert!(run(&["*", &filename]).is_err());
+    }
+
+    #[test]
+    fn test_count_invert_match_outcome() {
+        let dir = tempfile::tempdir().unwrap();
+        let all_foxes = write_file(dir.path(), "all_foxes.txt", "fox\nred fox\n");
+        let some_foxes = write_file(dir.path(), "some_foxes.txt", "fox\ndog\n");
+        let run = |flags: &[&str]| {
+            let run = run_grepr(flags).unwrap();
+            (run.output, run.outcome)
+        };
+
+        // Every line matches, so no inverted line is counted
+        assert_eq!(
+            run(&["-c", "-v", "fox", &all_foxes]),
+            (String::from("0\n"), SearchOutcome::NotMatched)
+        );
+
+        // One inverted line counted in any file is enough to succeed
+        assert_eq!(
+            run(&["-c", "-v", "fox", &all_foxes, &some_foxes]),
+            (
+                format!("{all_foxes}:0\n{some_foxes}:1\n"),
+                SearchOutcome::Matched
+            )
+        );
+    }
+
+    #[test]
+    fn test_find_lines_invalid_utf8() {
+        let text = b"fox one\nbad \xFF fox\n\xFF\xFE\nfox three\n";
+        let re = Regex::new("fox").unwrap();
+
+        // The line with the invalid byte neither ends the search nor hides the later lines
+        let matches: anyhow::Result<Vec<_>> =
+            find_lines(Cursor::new(&text), &re, false, 0, 0).collect();
+        assert_eq!(
+            matches.unwrap(),
+            [
+                FoundLine::Match(1, 0, "fox one\n".to_string()),
+                FoundLine::Match(2, 8, "bad \u{FFFD} fox\n".to_string()),
+                FoundLine::Match(4, 21, "fox three\n".to_string()),
+            ]
+        );
+
+        // The invalid line itself can be selected too
+        let matches: anyhow::Result<Vec<_>> =
+            find_lines(Cursor::new(&text), &re, true, 0, 0).collect();
+        assert_eq!(
+            matches.unwrap(),
+            [FoundLine::Match(3, 18, "\u{FFFD}\u{FFFD}\n".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_highlight_matches() {
+        let re = Regex::new("o+").unwrap();
+        assert_eq!(
+            highlight_matches("foo bar boo\n", &re),
+            "f\x1b[01;31moo\x1b[m bar b\x1b[01;31moo\x1b[m\n"
+        );
+        assert_eq!(highlight_matches("bar\n", &re), "bar\n");
+
+        // An empty match colors nothing
+        let re = Regex::new("x*").unwrap();
+        assert_eq!(highlight_matches("bar\n", &re), "bar\n");
+    }
+
+    #[test]
+    fn test_color() {
+        let dir = tempfile::tempdir().unwrap();
+        let filename = write_file(dir.path(), "fox.txt", "The quick brown fox\nfox\n");
+
+        assert_eq!(
+            run_grepr(&["--color=always", "-n", "quick", &filename])
+                .unwrap()
+                .output,
+            "\x1b[32m1\x1b[m:The \x1b[01;31mquick\x1b[m brown fox\n"
+        );
+
+        // The filename prefixes have a color of their own
+        assert_eq!(
+            run_grepr(&["--color=always", "^fox", &filename, &filename])
+                .unwrap()
+                .output,
+            format!(
+                "\x1b[35m{filename}\x1b[m:\x1b[01;31mfox\x1b[m\n\
+                 \x1b[35m{filename}\x1b[m:\x1b[01;31mfox\x1b[m\n"
+            )
+        );
+
+        // The output is plain when it is not a terminal or when the color is turned off. The
+        // tests share STDOUT with cargo, which may be a terminal.
+        assert_eq!(
+            run_grepr(&["--color=never", "quick", &filename])
+                .unwrap()
+                .output,
+            "The quick brown fox\n"
+        );
+        if !io::stdout().is_terminal() {
+            assert_eq!(
+                run_grepr(&["quick", &filename]).unwrap().output,
+                "The quick brown fox\n"
+            );
+        }
+    }
+
+    #[test]
+    fn test_threads() {
+        let dir = tempfile::tempdir().unwrap();
+        for index in 0..20 {
+            let text = format!("line {index}\nfox {index}\n");
+            write_file(dir.path(), &format!("{index:02}.txt"), text);
+        }
+        write_file(dir.path(), "10.txt", [b'f', b'o', b'x', 0xff, b'\n']);
+        let dirname = dir.path().display().to_string();
+        let missing = dir.path().join("missing.txt").display().to_string();
+
+        let run = |threads: &str, flags: &[&str]| {
+            let inputs = ["fox", &dirname, &missing];
+            run_grepr(&[&["-r", "-n", "--threads", threads], flags, &inputs].concat()).unwrap()
+        };
+
+        // The files are printed in the order of their paths, whatever the number of threads.
+        let sequential_run = run("1", &[]);
+        let lines: Vec<_> = sequential_run.output.lines().collect();
+        assert_eq!(lines.len(), 20);
+        assert_eq!(lines[0], format!("{dirname}/00.txt:2:fox 0"));
+        assert_eq!(lines[10], format!("{dirname}/10.txt:1:fox\u{FFFD}"));
+        assert_eq!(lines[19], format!("{dirname}/19.txt:2:fox 19"));
+        assert!(sequential_run
+            .error_output
+            .starts_with(&format!("{missing}: ")));
+        assert_eq!(sequential_run.outcome, SearchOutcome::Failed);
+
+        for threads in ["0", "4"] {
+            assert_eq!(run(threads, &[]), sequential_run);
+        }
+
+        // The groups of different files are separated the same way, whatever the number of
+        // threads.
+        let output = run("1", &["-B", "1"]).output;
+        assert!(output.starts_with(&format!(
+            "{dirname}/00.txt-1-line 0\n{dirname}/00.txt:2:fox 0\n--\n{dirname}/01.txt-1-line 1\n"
+        )));
+        assert_eq!(run("4", &["-B", "1"]).output, output);
+    }
 }
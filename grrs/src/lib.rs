@@ -1,21 +1,60 @@
-use anyhow::Result;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use anyhow::{Context, Result};
+use regex::RegexBuilder;
+use std::io::BufRead;
 
-pub fn find_matches(f: File, pattern: &str, mut writer: impl std::io::Write) -> Result<()> {
-    // BufReader.lines() reads a file more efficiently than std::fs::read_to_string().
-    let reader: BufReader<File> = BufReader::new(f);
+// How find_matches matches and prints the lines. The default matches case-sensitively and prints
+// every matching line as it is.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MatchOptions {
+    // Match the pattern regardless of case
+    pub ignore_case: bool,
+    // Prefix each matching line with its line number
+    pub line_number: bool,
+    // Print only the number of matching lines
+    pub count: bool,
+}
+
+// Taking any BufRead lets the same function read from a file, stdin or an in-memory Cursor.
+pub fn find_matches(
+    reader: impl BufRead,
+    pattern: &str,
+    options: MatchOptions,
+    mut writer: impl std::io::Write,
+) -> Result<()> {
+    // Compile the pattern once, rather than for every line. The regex engine handles the case
+    // folding itself.
+    let pattern = RegexBuilder::new(pattern)
+        .case_insensitive(options.ignore_case)
+        .build()
+        .with_context(|| format!("invalid pattern `{}`", pattern))?;
+
+    let mut match_count = 0;
 
-    for line in reader.lines() {
+    // Line numbers count every line read, starting from 1, not only the matching ones.
+    for (line_index, line) in reader.lines().enumerate() {
         // With a question mark, Rust will internally expand the Result.
         let s = line?;
 
-        if s.contains(&pattern) {
-            // writeln!() returns an io::Result because writing can fail.
-            writeln!(writer, "{}", s)?;
+        // Even with -i, the line is printed as it is.
+        if pattern.is_match(&s) {
+            match_count += 1;
+
+            // With -c, only the number of matches is printed, after all the lines are read.
+            if !options.count {
+                // writeln!() returns an io::Result because writing can fail.
+                if options.line_number {
+                    writeln!(writer, "{}:{}", line_index + 1, s)?;
+                } else {
+                    writeln!(writer, "{}", s)?;
+                }
+            }
         }
     }
 
+    if options.count {
+        writeln!(writer, "{}", match_count)?;
+    }
+
     // This means "Result is OK and has no content."
     // The last expression of any block in Rust is its return value.
     Ok(())
@@ -1,32 +1,47 @@
 use anyhow::{Context, Result};
 use clap::Parser;
-use std::fs::File;
-use std::io::stdout;
-use std::path::PathBuf;
+use cli_common::open_input;
+use std::io::{stdout, BufRead};
 
 // Search for a pattern in a file and display the lines that contain it.
 #[derive(Parser)]
 struct Cli {
-    // The pattern to look for
+    // The regular expression to look for
     pattern: String,
-    // The path to the file to read
-    // PathBuf is like a String but for file system paths that work cross-platform.
-    path: PathBuf,
+    // The path to the file to read; "-" or no path reads stdin
+    #[arg(default_value = "-")]
+    path: String,
+    // Match the pattern regardless of case
+    #[arg(short, long)]
+    ignore_case: bool,
+    // Prefix each matching line with its line number
+    #[arg(short = 'n', long)]
+    line_number: bool,
+    // Print only the number of matching lines
+    #[arg(short, long)]
+    count: bool,
 }
 
-// Box<dyn std::error::Error> can contain any type that implements the standard Error trait. So we
-// can use `?` on all of the usual functions that return Results.
-fn main() -> Result<()> {
+fn main() {
     // Example
     //   $ cargo run --quiet -- main src/main.rs
 
     // Cli::parse() is meant to be used in our main(); don't use it in other places.
     let args: Cli = Cli::parse();
 
-    let f: File =
-        File::open(&args.path).with_context(|| format!("could not read file `{:?}`", args.path))?;
+    if let Err(e) = run(args) {
+        cli_common::exit_with_error(e, 1);
+    }
+}
 
-    let _: Result<()> = grrs::find_matches(f, &args.pattern, &mut stdout());
+// Returning anyhow's Result lets us use `?` on all of the usual functions that return Results.
+fn run(args: Cli) -> Result<()> {
+    let reader: Box<dyn BufRead> = open_input(&args.path).context("could not read file")?;
 
-    Ok(())
+    let options = grrs::MatchOptions {
+        ignore_case: args.ignore_case,
+        line_number: args.line_number,
+        count: args.count,
+    };
+    grrs::find_matches(reader, &args.pattern, options, &mut stdout())
 }
@@ -1,5 +1,6 @@
 use assert_cmd::prelude::*; // Add methods on commands
 use assert_fs::prelude::*; // Used for creating temporary files
+use grrs::MatchOptions;
 use predicates::prelude::*; // Used for writing assertions
 use std::process::Command; // Run programs
 
@@ -28,3 +29,154 @@ fn find_content_in_file() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn find_content_in_cursor() -> Result<(), Box<dyn std::error::Error>> {
+    let input = std::io::Cursor::new("A test\nActual content\nMore content\nAnother test");
+    let mut output = Vec::new();
+
+    grrs::find_matches(input, "test", MatchOptions::default(), &mut output)?;
+    assert_eq!(output, b"A test\nAnother test\n");
+
+    Ok(())
+}
+
+#[test]
+fn find_content_in_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    for args in [vec!["test"], vec!["test", "-"]] {
+        // assert_cmd's own Command can feed stdin to the program.
+        assert_cmd::Command::cargo_bin("grrs")?
+            .args(args)
+            .write_stdin("A test\nActual content\nAnother test\n")
+            .assert()
+            .success()
+            .stdout("A test\nAnother test\n");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn find_content_ignoring_case() -> Result<(), Box<dyn std::error::Error>> {
+    let mut output = Vec::new();
+    grrs::find_matches(
+        std::io::Cursor::new("HELLO\nworld\n"),
+        "hello",
+        MatchOptions::default(),
+        &mut output,
+    )?;
+    assert_eq!(output, b"");
+
+    // The matching line is printed with its original case.
+    let mut output = Vec::new();
+    grrs::find_matches(
+        std::io::Cursor::new("HELLO\nworld\n"),
+        "hello",
+        MatchOptions {
+            ignore_case: true,
+            ..Default::default()
+        },
+        &mut output,
+    )?;
+    assert_eq!(output, b"HELLO\n");
+
+    Ok(())
+}
+
+#[test]
+fn find_regex_matches() -> Result<(), Box<dyn std::error::Error>> {
+    let input = "foo bar\nbar foo\nfood\nf0o\n";
+
+    let mut output = Vec::new();
+    grrs::find_matches(
+        std::io::Cursor::new(input),
+        "^foo",
+        MatchOptions::default(),
+        &mut output,
+    )?;
+    assert_eq!(output, b"foo bar\nfood\n");
+
+    let mut output = Vec::new();
+    grrs::find_matches(
+        std::io::Cursor::new(input),
+        "f[0-9]o",
+        MatchOptions::default(),
+        &mut output,
+    )?;
+    assert_eq!(output, b"f0o\n");
+
+    Ok(())
+}
+
+#[test]
+fn invalid_regex() -> Result<(), Box<dyn std::error::Error>> {
+    let mut output = Vec::new();
+    let result = grrs::find_matches(
+        std::io::Cursor::new("foo\n"),
+        "foo(",
+        MatchOptions::default(),
+        &mut output,
+    );
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().to_string(), "invalid pattern `foo(`");
+
+    let mut cmd = Command::cargo_bin("grrs")?;
+    cmd.arg("foo(").arg("-");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid pattern `foo(`"));
+
+    Ok(())
+}
+
+#[test]
+fn find_content_with_line_numbers() -> Result<(), Box<dyn std::error::Error>> {
+    let input = std::io::Cursor::new("A test\nActual content\nMore content\nAnother test\n");
+    let mut output = Vec::new();
+
+    // The non-matching lines are counted too.
+    grrs::find_matches(
+        input,
+        "test",
+        MatchOptions {
+            line_number: true,
+            ..Default::default()
+        },
+        &mut output,
+    )?;
+    assert_eq!(output, b"1:A test\n4:Another test\n");
+
+    Ok(())
+}
+
+#[test]
+fn count_matches() -> Result<(), Box<dyn std::error::Error>> {
+    let input = "A test\nActual content\nMore content\nAnother test\n";
+
+    let mut output = Vec::new();
+    grrs::find_matches(
+        std::io::Cursor::new(input),
+        "test",
+        MatchOptions {
+            count: true,
+            ..Default::default()
+        },
+        &mut output,
+    )?;
+    assert_eq!(output, b"2\n");
+
+    // Zero is printed too when nothing matches.
+    let mut output = Vec::new();
+    grrs::find_matches(
+        std::io::Cursor::new(input),
+        "nothing",
+        MatchOptions {
+            count: true,
+            ..Default::default()
+        },
+        &mut output,
+    )?;
+    assert_eq!(output, b"0\n");
+
+    Ok(())
+}
@@ -1,9 +1,7 @@
 use anyhow::Result;
 use clap::Parser;
-use std::{
-    fs::File,
-    io::{self, BufRead, BufReader, Read},
-};
+use cli_common::{exit_with_error, is_broken_pipe, open_input};
+use std::io::{self, BufRead, Write};
 
 /// Print the first 10 lines of each FILE to standard output.
 #[derive(Parser, Debug)]
@@ -22,90 +20,435 @@ struct Args {
     )]
     lines: u64,
 
-    /// Number of bytes
+    /// Number of bytes, with an optional suffix such as K (1024) or KB (1000); with a leading '-',
+    /// print all but the last BYTES bytes
     #[arg(
       short = 'c',
       long,
       conflicts_with = "lines",
-      value_parser = clap::value_parser!(u64).range(1..),
+      allow_negative_numbers = true,
+      value_parser = parse_byte_count,
     )]
-    bytes: Option<u64>,
+    bytes: Option<ByteCount>,
+
+    /// Do not end the byte output in the middle of a multibyte UTF-8 character
+    #[arg(long, requires = "bytes")]
+    char_safe: bool,
+
+    /// Never print headers giving file names
+    #[arg(short, long, visible_alias = "silent", conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Always print headers giving file names
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+// Represents how many bytes to print from the start of a file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ByteCount {
+    // Print the first N bytes.
+    First(u64),
+    // Print all but the last N bytes.
+    AllButLast(u64),
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    if let Err(e) = run(args) {
-        eprintln!("{e}");
-        std::process::exit(1);
+    match run(args, io::stdout().lock()) {
+        Err(e) => exit_with_error(e, 1),
+        // The failed files have already been reported.
+        Ok(false) => std::process::exit(1),
+        Ok(true) => (),
     }
 
     Ok(())
 }
 
-fn run(args: Args) -> Result<()> {
-    let file_count = args.files.len();
+// Run the program with parsed arguments, writing the output to the writer. Returns whether every
+// file was printed; a file that fails is reported to STDERR without stopping the others.
+fn run(args: Args, mut writer: impl Write) -> Result<bool> {
+    // Print the headers when there are multiple files, unless told otherwise.
+    let should_print_headers = match (args.quiet, args.verbose) {
+        (true, _) => false,
+        (_, true) => true,
+        _ => args.files.len() > 1,
+    };
+
+    let mut has_failed = false;
 
     for (file_index, filename) in args.files.iter().enumerate() {
-        match open_input_source(&filename) {
+        match open_input(filename) {
             Err(e) => {
-                eprintln!("{filename}: {e}");
+                eprintln!("{e}");
+                has_failed = true;
             }
-            // Accept the filehandle as a mutable value.
-            Ok(mut filehandle) => {
-                // Only print headers when there are multiple files.
-                if file_count > 1 {
+            Ok(filehandle) => {
+                if should_print_headers {
                     let linebreak = if file_index > 0 { "\n" } else { "" };
-                    println!("{linebreak}==> {filename} <==")
+                    writeln!(writer, "{linebreak}==> {filename} <==")?
                 }
 
-                // Check if args.bytes is some number of bytes to read.
-                if let Some(requested_byte_count) = args.bytes {
-                    // This branch is to support the BYTES option.
-
-                    // Read the desired number of bytes from a file. Be sure to add to our imports the trait
-                    // std::io::Read. We must indicate that we want a Vec (size known), not a slice
-                    // (size unknown).
-                    let bytes_read = filehandle
-                        .bytes()
-                        .take(requested_byte_count as usize)
-                        .collect::<Result<Vec<_>, _>>()?;
-
-                    // Convert the selected bytes into a string, which can be invalid UTF-8.
-                    // The size for bytes must be known at complile-time.
-                    print!("{}", String::from_utf8_lossy(&bytes_read));
-                } else {
-                    // Create a new empty mutable string buffer to hold each line.
-                    let mut line = String::new();
-
-                    // Iterate through a std::ops::Range to count up from zero to the requested number
-                    // of lines.
-                    for _ in 0..args.lines {
-                        // Read the next line into the string buffer.
-                        let bytes_read = filehandle.read_line(&mut line)?;
-
-                        // Break out of the loop when reaching the end of the file.
-                        if bytes_read == 0 {
-                            break;
-                        }
-
-                        // Print the line including the original line ending.
-                        print!("{line}");
-
-                        // Empty the line buffer.
-                        line.clear();
+                // A read error, such as reading a directory, only ends this file, but a closed pipe
+                // ends the whole program.
+                if let Err(e) = print_head(filehandle, &args, &mut writer) {
+                    if is_broken_pipe(&e) {
+                        return Err(e);
                     }
+                    eprintln!("{filename}: {e}");
+                    has_failed = true;
                 }
             }
         }
     }
 
+    Ok(!has_failed)
+}
+
+// Prints the first lines or bytes of a file.
+fn print_head(mut filehandle: impl BufRead, args: &Args, mut writer: impl Write) -> Result<()> {
+    // Check if args.bytes is some number of bytes to read.
+    if let Some(byte_count) = args.bytes {
+        // This branch is to support the BYTES option.
+        print_bytes(filehandle, byte_count, args.char_safe, writer)?;
+    } else {
+        // Create a new empty mutable string buffer to hold each line.
+        let mut line = String::new();
+
+        // Iterate through a std::ops::Range to count up from zero to the requested number of
+        // lines.
+        for _ in 0..args.lines {
+            // Read the next line into the string buffer.
+            let bytes_read = filehandle.read_line(&mut line)?;
+
+            // Break out of the loop when reaching the end of the file.
+            if bytes_read == 0 {
+                break;
+            }
+
+            // Print the line including the original line ending.
+            write!(writer, "{line}")?;
+
+            // Empty the line buffer.
+            line.clear();
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses the value of the BYTES option. A leading '-' selects all but the last N bytes.
+fn parse_byte_count(text: &str) -> Result<ByteCount, String> {
+    let (make_byte_count, size): (fn(u64) -> ByteCount, &str) = match text.strip_prefix('-') {
+        Some(size) => (ByteCount::AllButLast, size),
+        None => (ByteCount::First, text),
+    };
+
+    match parse_size(size)? {
+        0 => Err(format!("{text} is not in 1..{}", u64::MAX)),
+        n => Ok(make_byte_count(n)),
+    }
+}
+
+/// Parses a number followed by an optional multiplier suffix, like GNU head. The suffix "b" means
+/// 512-byte blocks, "K", "M", and "G" (or "KiB", "MiB", and "GiB") are powers of 1024, and "KB",
+/// "MB", and "GB" (or "kB") are powers of 1000.
+fn parse_size(text: &str) -> Result<u64, String> {
+    let suffix_start = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (digits, suffix) = text.split_at(suffix_start);
+
+    let multiplier: u64 = match suffix {
+        "" => 1,
+        "b" => 512,
+        "K" | "KiB" => 1024,
+        "KB" | "kB" => 1000,
+        "M" | "MiB" => 1024 * 1024,
+        "MB" => 1000 * 1000,
+        "G" | "GiB" => 1024 * 1024 * 1024,
+        "GB" => 1000 * 1000 * 1000,
+        // Only a number followed by letters is a malformed suffix; anything else is left for the
+        // number parser to report.
+        _ if !digits.is_empty() && suffix.chars().all(|c| c.is_ascii_alphabetic()) => {
+            return Err(format!(
+                r#"invalid suffix "{suffix}"; use b, K, KB, M, MB, G, or GB"#
+            ));
+        }
+        _ => 1,
+    };
+
+    // Parse the whole text without a known suffix, so that the error points at the bad digit.
+    let digits = if multiplier == 1 { text } else { digits };
+
+    digits
+        .parse::<u64>()
+        .map_err(|e| e.to_string())?
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("{text} is too large"))
+}
+
+/// Prints the requested bytes from the start of the filehandle. The bytes are displayed as
+/// UTF-8, with invalid sequences shown as replacement characters.
+fn print_bytes(
+    filehandle: impl BufRead,
+    byte_count: ByteCount,
+    char_safe: bool,
+    mut writer: impl Write,
+) -> Result<()> {
+    match byte_count {
+        ByteCount::First(n) => {
+            // Copy one buffer at a time so that a huge count does not have to fit in memory.
+            let mut filehandle = filehandle.take(n);
+
+            // A character can be split across two buffers, so hold back its first bytes until the
+            // rest of it has been read.
+            let mut pending = vec![];
+
+            loop {
+                let buffer = filehandle.fill_buf()?;
+                if buffer.is_empty() {
+                    break;
+                }
+
+                pending.extend_from_slice(buffer);
+                let buffer_len = buffer.len();
+                filehandle.consume(buffer_len);
+
+                let complete_len = trim_incomplete_char(&pending).len();
+                write!(
+                    writer,
+                    "{}",
+                    String::from_utf8_lossy(&pending[..complete_len])
+                )?;
+                pending.drain(..complete_len);
+            }
+
+            if !char_safe {
+                write!(writer, "{}", String::from_utf8_lossy(&pending))?;
+            }
+        }
+        ByteCount::AllButLast(n) => {
+            // The end of the file is unknown until everything has been read.
+            let mut filehandle = filehandle;
+            let mut buffer = vec![];
+            filehandle.read_to_end(&mut buffer)?;
+            buffer.truncate(buffer.len().saturating_sub(n as usize));
+
+            if char_safe {
+                let char_safe_len = trim_incomplete_char(&buffer).len();
+                buffer.truncate(char_safe_len);
+            }
+
+            write!(writer, "{}", String::from_utf8_lossy(&buffer))?;
+        }
+    }
+
     Ok(())
 }
 
-fn open_input_source(filename: &str) -> Result<Box<dyn BufRead>> {
-    match filename {
-        "-" => Ok(Box::new(BufReader::new(io::stdin()))),
-        _ => Ok(Box::new(BufReader::new(File::open(filename)?))),
+/// Removes an incomplete UTF-8 sequence from the end of the bytes so that the output does not end
+/// with a replacement character. Invalid bytes elsewhere are left alone.
+fn trim_incomplete_char(bytes: &[u8]) -> &[u8] {
+    // A character is at most four bytes long, so walk back over at most three continuation bytes
+    // (0b10xxxxxx) to find the lead byte of the last character.
+    for (index_from_end, &byte) in bytes.iter().rev().take(4).enumerate() {
+        if byte & 0b1100_0000 != 0b1000_0000 {
+            // The lead byte tells how many bytes the character needs.
+            let needed = match byte {
+                0b1100_0000..=0b1101_1111 => 2,
+                0b1110_0000..=0b1110_1111 => 3,
+                0b1111_0000..=0b1111_0111 => 4,
+                _ => 1,
+            };
+            let available = index_from_end + 1;
+
+            if available < needed {
+                return &bytes[..bytes.len() - available];
+            }
+
+            break;
+        }
+    }
+
+    bytes
+}
+
+// Unit tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::io::{BufReader, Cursor};
+
+    #[test]
+    fn test_parse_byte_count() {
+        assert_eq!(parse_byte_count("3"), Ok(ByteCount::First(3)));
+        assert_eq!(parse_byte_count("-3"), Ok(ByteCount::AllButLast(3)));
+        assert!(parse_byte_count("0").is_err());
+        assert!(parse_byte_count("-0").is_err());
+        assert!(parse_byte_count("foo").is_err());
+        assert!(parse_byte_count("--3").is_err());
+    }
+
+    // Runs headr over two small files with the given flags.
+    fn run_files(flags: &[&str], file_count: usize) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        let mut argv = vec!["headr".to_string(), "-n".to_string(), "1".to_string()];
+        argv.extend(flags.iter().map(|flag| flag.to_string()));
+
+        for name in ["one.txt", "two.txt"].iter().take(file_count) {
+            let path = dir.path().join(name);
+            std::fs::write(&path, format!("{name}\nmore\n")).unwrap();
+            argv.push(path.display().to_string());
+        }
+
+        let mut output = vec![];
+        assert!(run(Args::parse_from(argv), &mut output).unwrap());
+        String::from_utf8(output)
+            .unwrap()
+            .replace(&dir.path().display().to_string(), "DIR")
+    }
+
+    #[test]
+    fn test_headers() {
+        // By default, only multiple files get headers.
+        assert_eq!(run_files(&[], 1), "one.txt\n");
+        assert_eq!(
+            run_files(&[], 2),
+            "==> DIR/one.txt <==\none.txt\n\n==> DIR/two.txt <==\ntwo.txt\n"
+        );
+
+        // -v prints the header even for a single file.
+        assert_eq!(run_files(&["-v"], 1), "==> DIR/one.txt <==\none.txt\n");
+        assert_eq!(run_files(&["--verbose"], 1), run_files(&["-v"], 1));
+
+        // -q suppresses the headers even for multiple files.
+        assert_eq!(run_files(&["-q"], 2), "one.txt\ntwo.txt\n");
+        assert_eq!(run_files(&["--silent"], 2), run_files(&["-q"], 2));
+
+        assert!(Args::try_parse_from(["headr", "-q", "-v"]).is_err());
+    }
+
+    #[test]
+    fn test_read_error_continues() {
+        let dir = tempfile::tempdir().unwrap();
+        // A directory can be opened, but reading it fails.
+        let unreadable = dir.path().join("unreadable");
+        std::fs::create_dir(&unreadable).unwrap();
+        let readable = dir.path().join("readable.txt");
+        std::fs::write(&readable, "one\ntwo\n").unwrap();
+
+        for flags in [&["-n", "1"][..], &["-c", "4"]] {
+            let mut argv = vec!["headr".to_string()];
+            argv.extend(flags.iter().map(|flag| flag.to_string()));
+            argv.push(unreadable.display().to_string());
+            argv.push(readable.display().to_string());
+
+            // The readable file is still printed, but the run reports the failure.
+            let mut output = vec![];
+            assert!(!run(Args::parse_from(argv), &mut output).unwrap());
+            assert_eq!(
+                String::from_utf8(output)
+                    .unwrap()
+                    .replace(&dir.path().display().to_string(), "DIR"),
+                "==> DIR/unreadable <==\n\n==> DIR/readable.txt <==\none\n",
+                "{flags:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("3"), Ok(3));
+        assert_eq!(parse_size("1K"), Ok(1024));
+        assert_eq!(parse_size("1KiB"), Ok(1024));
+        assert_eq!(parse_size("1KB"), Ok(1000));
+        assert_eq!(parse_size("1kB"), Ok(1000));
+        assert_eq!(parse_size("2M"), Ok(2 * 1024 * 1024));
+        assert_eq!(parse_size("2MB"), Ok(2_000_000));
+        assert_eq!(parse_size("1G"), Ok(1024 * 1024 * 1024));
+        assert_eq!(parse_size("1GB"), Ok(1_000_000_000));
+
+        // The "b" suffix counts 512-byte blocks, so 512b is 512 * 512 bytes, not 512 bytes.
+        assert_eq!(parse_size("1b"), Ok(512));
+        assert_eq!(parse_size("512b"), Ok(512 * 512));
+
+        // A malformed suffix is named in the error.
+        assert_eq!(
+            parse_size("1X"),
+            Err(r#"invalid suffix "X"; use b, K, KB, M, MB, G, or GB"#.to_string())
+        );
+        assert!(parse_size("K").is_err());
+        assert!(parse_size("1.5K").is_err());
+        assert!(parse_size("99999999999999999999G").is_err());
+        assert!(parse_size("18446744073709551615K").is_err());
+
+        // A suffix works with a leading '-' too.
+        assert_eq!(parse_byte_count("-1K"), Ok(ByteCount::AllButLast(1024)));
+    }
+
+    fn head_bytes(input: &[u8], byte_count: ByteCount, char_safe: bool) -> String {
+        let mut output = vec![];
+        print_bytes(Cursor::new(input), byte_count, char_safe, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_print_bytes() {
+        // "é" is the two bytes 0xC3 0xA9.
+        let text = "abcé".as_bytes();
+
+        assert_eq!(head_bytes(text, ByteCount::First(2), false), "ab");
+        assert_eq!(head_bytes(text, ByteCount::First(10), false), "abcé");
+        assert_eq!(head_bytes(text, ByteCount::AllButLast(2), false), "abc");
+        assert_eq!(head_bytes(text, ByteCount::AllButLast(10), false), "");
+
+        // Without --char-safe, splitting "é" leaves a replacement character.
+        assert_eq!(head_bytes(text, ByteCount::First(4), false), "abc\u{FFFD}");
+        assert_eq!(
+            head_bytes(text, ByteCount::AllButLast(1), false),
+            "abc\u{FFFD}"
+        );
+
+        // With --char-safe, the output backs off to the character boundary.
+        assert_eq!(head_bytes(text, ByteCount::AllButLast(1), true), "abc");
+        assert_eq!(head_bytes(text, ByteCount::First(4), true), "abc");
+        assert_eq!(head_bytes(text, ByteCount::First(5), true), "abcé");
+    }
+
+    #[test]
+    fn test_print_bytes_across_buffers() {
+        // A one-byte buffer splits every multibyte character across reads.
+        let text = "añ€😀".repeat(3);
+        let mut output = vec![];
+        let filehandle = BufReader::with_capacity(1, Cursor::new(text.as_bytes()));
+        print_bytes(filehandle, ByteCount::First(1000), false, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), text);
+
+        // Invalid bytes are still replaced one by one.
+        let mut output = vec![];
+        let filehandle = BufReader::with_capacity(1, Cursor::new(b"a\xFF\xC3b"));
+        print_bytes(filehandle, ByteCount::First(4), false, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "a\u{FFFD}\u{FFFD}b");
+    }
+
+    #[test]
+    fn test_trim_incomplete_char() {
+        // Complete characters of every width are kept.
+        assert_eq!(trim_incomplete_char(b"a"), b"a");
+        assert_eq!(trim_incomplete_char("é".as_bytes()), "é".as_bytes());
+        assert_eq!(trim_incomplete_char("日".as_bytes()), "日".as_bytes());
+        assert_eq!(trim_incomplete_char("🦀".as_bytes()), "🦀".as_bytes());
+
+        // Partial characters at the end are removed.
+        assert_eq!(trim_incomplete_char(&"a日".as_bytes()[..3]), b"a");
+        assert_eq!(trim_incomplete_char(&"a🦀".as_bytes()[..4]), b"a");
+
+        // Invalid bytes that are not an incomplete tail are left alone.
+        assert_eq!(trim_incomplete_char(b"\xA9a"), b"\xA9a");
+        assert_eq!(trim_incomplete_char(b""), b"");
     }
 }
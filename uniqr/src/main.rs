@@ -1,96 +1,268 @@
 use anyhow::{anyhow, Result};
 use clap::Parser;
+use cli_common::{exit_with_error, open_input};
 use std::{
     fs::File,
-    io::{self, BufRead, BufReader, Write},
+    io::{self, BufRead, BufReader, Read, Write},
+    mem,
 };
 
 /// Report or omit repeated lines
 #[derive(Debug, Parser, Clone)]
 #[command(author, version, about)]
 struct Args {
-    /// Input file
+    /// Input file(s), read as if they were concatenated
     #[arg(value_name = "INPUT", default_value = "-")]
-    in_file: String,
+    in_files: Vec<String>,
 
     /// Output file
-    #[arg(value_name = "OUTPUT")]
+    #[arg(short, long = "output", value_name = "OUTPUT")]
     out_file: Option<String>,
 
     /// Prefix lines by the number of occurrences
     #[arg(short, long)]
     count: bool,
+
+    /// Ignore differences in case when comparing lines
+    #[arg(short, long)]
+    ignore_case: bool,
+
+    /// Avoid comparing the first N fields, which are runs of blanks followed by non-blanks
+    #[arg(short = 'f', long, value_name = "N", default_value_t = 0)]
+    skip_fields: usize,
+
+    /// Avoid comparing the first N characters, after the skipped fields
+    #[arg(short = 's', long, value_name = "N", default_value_t = 0)]
+    skip_chars: usize,
+
+    /// Compare no more than N characters, after the skipped fields and characters
+    #[arg(short = 'w', long, value_name = "N")]
+    check_chars: Option<usize>,
+
+    /// Only print duplicate lines, one for each group
+    #[arg(short = 'd', long)]
+    repeated: bool,
+
+    /// Print every line, separating the groups with a blank line placed by METHOD
+    #[arg(
+        long,
+        value_name = "METHOD",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "separate",
+        conflicts_with_all = ["count", "repeated", "total"]
+    )]
+    group: Option<GroupMethod>,
+
+    /// Line delimiter is NUL, not newline
+    #[arg(short, long)]
+    zero_terminated: bool,
+
+    /// Print the total number of duplicate lines removed to STDERR
+    #[arg(long)]
+    total: bool,
+}
+
+// Represents where --group places the blank lines
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum GroupMethod {
+    /// Between the groups
+    Separate,
+    /// Before each group
+    Prepend,
+    /// After each group
+    Append,
+    /// Before and after each group
+    Both,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    if let Err(e) = do_run(args) {
-        eprintln!("{e}");
-        std::process::exit(1);
+    if let Err(e) = do_run(args, io::stderr()) {
+        exit_with_error(e, 1);
     }
 
     Ok(())
 }
 
-fn do_run(args: Args) -> Result<()> {
-    // Create an informative error message on failure.
-    let mut in_filehandle =
-        open_input_file(&args.in_file).map_err(|e| anyhow!("{}: {}", args.in_file, e))?;
+// Run the program with parsed arguments, writing the summary for --total to the error writer.
+fn do_run(args: Args, mut error_writer: impl Write) -> Result<()> {
+    // Read the input files or STDIN as a single stream, like cat, so that a group of identical
+    // lines can span the files. Create an informative error message on failure.
+    let mut in_filehandle: Box<dyn Read> = Box::new(io::empty());
+    for in_file in &args.in_files {
+        let filehandle = open_input(in_file)?;
+        in_filehandle = Box::new(in_filehandle.chain(filehandle));
+    }
 
-    let mut out_filehandle: Box<dyn Write> =
+    let out_filehandle: Box<dyn Write> =
         open_output_file(&args.out_file).map_err(|e| anyhow!("{:?}: {}", args.out_file, e))?;
 
-    // This closure must be declared as mutable because the out_filehandle is borrowed as a mutable
-    // value.
-    let mut print_info_row = |n: u64, s: &str| -> Result<()> {
+    let removed_count = uniq(BufReader::new(in_filehandle), out_filehandle, &args)?;
+
+    // Each group is printed once, so the other lines of the group were removed.
+    if args.total {
+        writeln!(error_writer, "{removed_count} duplicate lines removed")?;
+    }
+
+    Ok(())
+}
+
+// Writes each group of adjacent identical lines of the reader once, as selected by the arguments.
+// Returns the number of lines that were not printed because they repeat the first line of their
+// group.
+fn uniq(mut reader: impl BufRead, mut writer: impl Write, args: &Args) -> Result<u64> {
+    // Count the input lines and the groups of identical lines for the summary.
+    let mut line_count: u64 = 0;
+    let mut group_count: u64 = 0;
+
+    // This closure must be declared as mutable because it updates the group count. The writer is
+    // passed to it because the lines of --group are written outside of it.
+    let mut print_info_row = |writer: &mut dyn Write, n: u64, s: &str| -> Result<()> {
         // Print the output only when count is greater than 0.
         if n > 0 {
+            group_count += 1;
+
+            // With --repeated, the lines that appear only once are not printed. With --group, the
+            // lines have already been printed one by one.
+            if (args.repeated && n == 1) || args.group.is_some() {
+                return Ok(());
+            }
+
             if args.count {
-                write!(out_filehandle, "{:>4} {}", n, s)?;
+                // Like GNU uniq, the counts are right-aligned in a field of 7 columns.
+                write!(writer, "{:>7} {}", n, s)?;
             } else {
-                write!(out_filehandle, "{}", s)?;
+                write!(writer, "{}", s)?;
             }
         }
 
         Ok(())
     };
 
+    // Decide whether two lines belong to the same group. The lines are compared without their line
+    // endings, and the first line of a group is printed as it is.
+    let is_same_group = |line1: &str, line2: &str| -> bool {
+        // Only the terminator of each record is stripped, so that a newline within a record of
+        // --zero-terminated, or trailing whitespace, still tells the records apart.
+        let record_terminator = if args.zero_terminated { '\0' } else { '\n' };
+        let line1 = line1.strip_suffix(record_terminator).unwrap_or(line1);
+        let line2 = line2.strip_suffix(record_terminator).unwrap_or(line2);
+
+        let key1 = comparison_key(line1, args.skip_fields, args.skip_chars, args.check_chars);
+        let key2 = comparison_key(line2, args.skip_fields, args.skip_chars, args.check_chars);
+
+        if args.ignore_case {
+            key1.to_lowercase() == key2.to_lowercase()
+        } else {
+            key1 == key2
+        }
+    };
+
     // These buffers allow us to only allocate memory for the current and previout lines so our
     // program can scale to any file size.
     let mut current_line = String::new();
     let mut previous_line = String::new();
     let mut duplicate_count: u64 = 0;
 
-    // Read lines of text from an input file or STDIN, preserving the line endings.
+    // The blank lines of --group are empty records, so they are NULs with --zero-terminated.
+    let record_terminator = if args.zero_terminated { "\0" } else { "\n" };
+
+    // Read lines of text, preserving the line endings. The last line might not have one.
     loop {
-        let bytes_read = in_filehandle.read_line(&mut current_line)?;
+        let bytes_read = read_record(&mut reader, &mut current_line, args.zero_terminated)?;
 
         if bytes_read == 0 {
             break;
         }
 
-        let is_different_from_previous = current_line.trim_end() != previous_line.trim_end();
+        // The first line always starts a group, even when it is blank.
+        let is_new_group = duplicate_count == 0 || !is_same_group(&current_line, &previous_line);
+
+        // With --group, every line is printed as it is read, and a blank line marks the start of
+        // each group but the first one, which only gets one to be prepended.
+        if let Some(group_method) = args.group {
+            let is_first_group = duplicate_count == 0;
+            let is_prepended = matches!(group_method, GroupMethod::Prepend | GroupMethod::Both);
+
+            if is_new_group && (!is_first_group || is_prepended) {
+                write!(writer, "{record_terminator}")?;
+            }
 
-        if is_different_from_previous {
-            print_info_row(duplicate_count, &previous_line)?;
-            previous_line = current_line.clone();
+            write!(writer, "{current_line}")?;
+        }
+
+        if is_new_group {
+            print_info_row(&mut writer, duplicate_count, &previous_line)?;
+            mem::swap(&mut previous_line, &mut current_line);
             duplicate_count = 0;
         }
 
         duplicate_count += 1;
+        line_count += 1;
         current_line.clear();
     }
 
-    print_info_row(duplicate_count, &previous_line)?;
+    print_info_row(&mut writer, duplicate_count, &previous_line)?;
 
-    Ok(())
+    if matches!(args.group, Some(GroupMethod::Append | GroupMethod::Both)) && line_count > 0 {
+        write!(writer, "{record_terminator}")?;
+    }
+
+    writer.flush()?;
+
+    Ok(line_count - group_count)
 }
 
-fn open_input_file(filename: &str) -> Result<Box<dyn BufRead>> {
-    match filename {
-        "-" => Ok(Box::new(BufReader::new(io::stdin()))),
-        path => Ok(Box::new(BufReader::new(File::open(path)?))),
+// Appends the next record of the reader to the buffer, including its terminator, which is NUL
+// instead of newline with --zero-terminated. Returns the number of bytes read.
+fn read_record(
+    reader: &mut impl BufRead,
+    record: &mut String,
+    zero_terminated: bool,
+) -> io::Result<usize> {
+    if !zero_terminated {
+        return reader.read_line(record);
+    }
+
+    // Like the lines, the records must be valid UTF-8.
+    let mut bytes = vec![];
+    let bytes_read = reader.read_until(b'\0', &mut bytes)?;
+    let text =
+        String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    record.push_str(&text);
+
+    Ok(bytes_read)
+}
+
+// Returns the part of a line, given without its terminator, that decides its group: the rest of
+// the line after skipping the fields and then the characters, and limited to the checked
+// characters like GNU uniq.
+fn comparison_key(
+    line: &str,
+    skip_fields: usize,
+    skip_chars: usize,
+    check_chars: Option<usize>,
+) -> &str {
+    let is_blank = |c: char| c == ' ' || c == '\t';
+
+    let mut key = line;
+
+    for _ in 0..skip_fields {
+        key = key.trim_start_matches(is_blank);
+        key = key.trim_start_matches(|c| !is_blank(c));
+    }
+
+    key = match key.char_indices().nth(skip_chars) {
+        Some((index, _)) => &key[index..],
+        None => "",
+    };
+
+    match check_chars.and_then(|check_chars| key.char_indices().nth(check_chars)) {
+        Some((index, _)) => &key[..index],
+        None => key,
     }
 }
 
@@ -100,3 +272,242 @@ fn open_output_file(filename: &Option<String>) -> Result<Box<dyn Write>> {
         Some(path) => Ok(Box::new(File::create(path)?)),
     }
 }
+
+// Unit tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::{fs, io::Cursor};
+
+    // Runs the dedup loop over the input with the flags, returning the output.
+    fn run_uniqr(flags: &[&str], input: &str) -> String {
+        let args = Args::parse_from(["uniqr"].iter().chain(flags));
+        let mut output = vec![];
+        uniq(Cursor::new(input), &mut output, &args).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_uniq() {
+        let input = "a\na\nb\na\n";
+
+        assert_eq!(run_uniqr(&[], input), "a\nb\na\n");
+        assert_eq!(run_uniqr(&[], ""), "");
+
+        // A blank first line is a group of its own.
+        assert_eq!(run_uniqr(&["-c"], "\n\na\n"), "      2 \n      1 a\n");
+
+        // A last line without a line ending still joins its group, and is printed as it is.
+        assert_eq!(run_uniqr(&["-c"], "a\na"), "      2 a\n");
+        assert_eq!(run_uniqr(&["-c"], "a\nb"), "      1 a\n      1 b");
+    }
+
+    #[test]
+    fn test_count() {
+        assert_eq!(
+            run_uniqr(&["-c"], "a\na\nb\na\n"),
+            "      2 a\n      1 b\n      1 a\n"
+        );
+
+        // Like `uniq -c`, seven columns for the count and a single space before the line as it is.
+        assert_eq!(run_uniqr(&["-c"], "x\n  y\n"), "      1 x\n      1   y\n");
+
+        // A wider count widens its column.
+        let input = "a\n".repeat(12345) + "b\n";
+        assert_eq!(run_uniqr(&["-c"], &input), "  12345 a\n      1 b\n");
+    }
+
+    #[test]
+    fn test_repeated() {
+        let input = "a\nb\nb\nc\nd\nd\nd\na\n";
+
+        assert_eq!(run_uniqr(&["-d"], input), "b\nd\n");
+        assert_eq!(
+            run_uniqr(&["--repeated", "-c"], input),
+            "      2 b\n      3 d\n"
+        );
+        assert_eq!(run_uniqr(&["-d"], "a\nb\n"), "");
+        assert_eq!(run_uniqr(&["-d"], ""), "");
+    }
+
+    #[test]
+    fn test_ignore_case() {
+        // The first line of the group keeps its case in the output.
+        assert_eq!(
+            run_uniqr(&["-c", "-i"], "Foo\nfoo\nbar"),
+            "      2 Foo\n      1 bar"
+        );
+        assert_eq!(
+            run_uniqr(&["-c"], "Foo\nfoo\nbar"),
+            "      1 Foo\n      1 foo\n      1 bar"
+        );
+        assert_eq!(run_uniqr(&["--ignore-case"], "ÄRGER\närger\n"), "ÄRGER\n");
+    }
+
+    #[test]
+    fn test_skip() {
+        let line = "  10:00:01 GET\t/index.html";
+
+        assert_eq!(
+            comparison_key(line, 0, 0, None),
+            "  10:00:01 GET\t/index.html"
+        );
+        assert_eq!(comparison_key(line, 1, 0, None), " GET\t/index.html");
+        assert_eq!(comparison_key(line, 2, 0, None), "\t/index.html");
+        assert_eq!(comparison_key(line, 1, 1, None), "GET\t/index.html");
+        assert_eq!(comparison_key(line, 0, 4, None), ":00:01 GET\t/index.html");
+        assert_eq!(comparison_key(line, 3, 0, None), "");
+        assert_eq!(comparison_key(line, 0, 100, None), "");
+
+        // Characters are skipped, not bytes.
+        assert_eq!(comparison_key("éèa", 0, 2, None), "a");
+
+        let log = "\
+2024-05-01T10:00:00 disk full
+2024-05-01T10:00:05 disk full
+2024-05-01T10:01:00 disk ok
+";
+
+        // Skipping the timestamp field leaves the message, and its leading blank, to compare.
+        assert_eq!(
+            run_uniqr(&["--skip-fields", "1", "-c"], log),
+            "      2 2024-05-01T10:00:00 disk full\n      1 2024-05-01T10:01:00 disk ok\n"
+        );
+
+        // Skipping the date by characters compares the times as well.
+        assert_eq!(run_uniqr(&["--skip-chars", "11"], log), log);
+    }
+
+    #[test]
+    fn test_check_chars() {
+        assert_eq!(comparison_key("abcXXX", 0, 0, Some(3)), "abc");
+        assert_eq!(comparison_key("abcXXX", 0, 0, Some(10)), "abcXXX");
+        assert_eq!(comparison_key("abcXXX", 0, 0, Some(0)), "");
+        assert_eq!(comparison_key("1 éèaX", 1, 1, Some(3)), "éèa");
+
+        // The records share a fixed-width ID and differ after it.
+        let input = "ID0001 alpha\nID0001 beta\nID0002 alpha\nID0002\n";
+        assert_eq!(
+            run_uniqr(&["--check-chars", "6", "-c"], input),
+            "      2 ID0001 alpha\n      2 ID0002 alpha\n"
+        );
+
+        // Without the limit, every line is different.
+        assert_eq!(run_uniqr(&[], input), input);
+
+        // The limit applies after the skipping, and composes with --ignore-case.
+        assert_eq!(
+            run_uniqr(
+                &["-c", "-f1", "-s2", "-w2", "-i"],
+                "1 xAbQ\n2 yaBR\n3 zAC\n"
+            ),
+            "      2 1 xAbQ\n      1 3 zAC\n"
+        );
+    }
+
+    #[test]
+    fn test_group() {
+        let input = "a\na\nb\nc\n";
+
+        // The groups get a blank line between each pair of them, and none around them.
+        assert_eq!(run_uniqr(&["--group"], input), "a\na\n\nb\n\nc\n");
+        assert_eq!(run_uniqr(&["--group=separate"], input), "a\na\n\nb\n\nc\n");
+        assert_eq!(run_uniqr(&["--group=prepend"], input), "\na\na\n\nb\n\nc\n");
+        assert_eq!(run_uniqr(&["--group=append"], input), "a\na\n\nb\n\nc\n\n");
+        assert_eq!(run_uniqr(&["--group=both"], input), "\na\na\n\nb\n\nc\n\n");
+        assert_eq!(run_uniqr(&["--group=both"], ""), "");
+
+        // The groups follow the comparison options.
+        assert_eq!(run_uniqr(&["--group", "-i"], "a\nA\nb\n"), "a\nA\n\nb\n");
+
+        // Like GNU uniq, the lines of a group cannot be counted or selected.
+        assert!(Args::try_parse_from(["uniqr", "--group", "-c"]).is_err());
+        assert!(Args::try_parse_from(["uniqr", "--group", "-d"]).is_err());
+
+        // Every line is printed, so there are no removed lines to total.
+        assert!(Args::try_parse_from(["uniqr", "--group", "--total"]).is_err());
+        assert!(Args::try_parse_from(["uniqr", "--group=all"]).is_err());
+    }
+
+    #[test]
+    fn test_zero_terminated() {
+        let input = "a\0a\0b\nc\0b\nc\0d";
+
+        assert_eq!(run_uniqr(&["-z"], input), "a\0b\nc\0d");
+        assert_eq!(
+            run_uniqr(&["-z", "-c"], input),
+            "      2 a\0      2 b\nc\0      1 d"
+        );
+
+        // Newlines are part of the records.
+        assert_eq!(run_uniqr(&["-z"], "a\nb\0a\nb\0"), "a\nb\0");
+        assert_eq!(run_uniqr(&["-z"], "a\n\0a\0"), "a\n\0a\0");
+        assert_eq!(run_uniqr(&["-z", "--group"], "a\0a\0b\0"), "a\0a\0\0b\0");
+
+        // Like the output of `find -print0`, with adjacent duplicates.
+        assert_eq!(
+            run_uniqr(
+                &["--zero-terminated", "--count"],
+                "./a.txt\0./a.txt\0./b c.txt\0./a.txt\0"
+            ),
+            "      2 ./a.txt\0      1 ./b c.txt\0      1 ./a.txt\0"
+        );
+    }
+
+    #[test]
+    fn test_total() {
+        let dir = tempfile::tempdir().unwrap();
+        let in_file = dir.path().join("input.txt");
+        let out_file = dir.path().join("output.txt");
+        fs::write(&in_file, "a\na\nb\nc\nc\nc\na\n").unwrap();
+
+        let args = Args::parse_from([
+            "uniqr",
+            "--total",
+            &in_file.display().to_string(),
+            "-o",
+            &out_file.display().to_string(),
+        ]);
+        let mut error_output = vec![];
+        do_run(args, &mut error_output).unwrap();
+
+        // The summary goes to the error writer, leaving the output untouched.
+        assert_eq!(
+            String::from_utf8(error_output).unwrap(),
+            "3 duplicate lines removed\n"
+        );
+        assert_eq!(fs::read_to_string(&out_file).unwrap(), "a\nb\nc\na\n");
+    }
+
+    #[test]
+    fn test_multiple_inputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let in_file1 = dir.path().join("input1.txt");
+        let in_file2 = dir.path().join("input2.txt");
+        let in_file3 = dir.path().join("input3.txt");
+        let out_file = dir.path().join("output.txt");
+        fs::write(&in_file1, "a\nb\nb\n").unwrap();
+        fs::write(&in_file2, "b\nc\nc").unwrap();
+        fs::write(&in_file3, "\nd\n").unwrap();
+
+        let args = Args::parse_from([
+            "uniqr",
+            "-c",
+            &in_file1.display().to_string(),
+            &in_file2.display().to_string(),
+            &in_file3.display().to_string(),
+            "-o",
+            &out_file.display().to_string(),
+        ]);
+        do_run(args, io::sink()).unwrap();
+
+        // The "b" group spans the first two files, and the unterminated "c" of the second file is
+        // completed by the third one.
+        assert_eq!(
+            fs::read_to_string(&out_file).unwrap(),
+            "      1 a\n      3 b\n      2 c\n      1 d\n"
+        );
+    }
+}
@@ -1,7 +1,11 @@
 use anyhow::Result;
 use clap::Parser;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use cli_common::{exit_with_error, open_input};
+use std::{
+    fs,
+    io::{self, BufRead, BufWriter, Write},
+};
+use unicode_width::UnicodeWidthChar;
 
 /// Print newline, word, and byte counts for each FILE, and a total line if more than one FILE is
 /// specified.  A word is a non-zero-length sequence of printable characters delimited by white
@@ -28,6 +32,39 @@ struct Args {
     /// Show character count
     #[arg(short = 'm', long, conflicts_with = "bytes")]
     chars: bool,
+
+    /// Show the display width, where wide characters such as CJK take two columns and combining
+    /// marks take none
+    #[arg(long)]
+    width: bool,
+
+    /// Print the maximum display width
+    #[arg(short = 'L', long)]
+    max_line_length: bool,
+
+    /// Report the number of the longest line of each FILE to STDERR
+    #[arg(long)]
+    show_longest_line: bool,
+
+    /// When to print a line with total counts
+    #[arg(long, value_name = "WHEN", value_enum, default_value_t = TotalMode::Auto)]
+    total: TotalMode,
+
+    /// Read input from the files specified by NUL-terminated names in file F; If F is - then read
+    /// names from standard input
+    #[arg(long, value_name = "F")]
+    files0_from: Option<String>,
+}
+
+// Represents when the total line is printed
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum TotalMode {
+    /// Only when there is more than one FILE, like GNU wc by default
+    Auto,
+    /// Even for a single FILE
+    Always,
+    /// Not even for many FILEs
+    Never,
 }
 
 #[derive(Debug, PartialEq)]
@@ -36,26 +73,38 @@ struct FileInfo {
     word_count: usize,
     byte_count: usize,
     char_count: usize,
+    display_width: usize,
+    max_line_length: usize,
+    // The first line with the maximum length, or 0 when there are no lines
+    longest_line_number: usize,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    if let Err(e) = run(args) {
-        eprintln!("{e}");
-        std::process::exit(1);
+    match run(args) {
+        Err(e) => exit_with_error(e, 1),
+        // Like GNU wc, fail after counting the other files when the file list has an empty name.
+        Ok(false) => std::process::exit(1),
+        Ok(true) => Ok(()),
     }
-
-    Ok(())
 }
 
-fn run(mut args: Args) -> Result<()> {
+// Returns whether every name in the --files0-from list was valid.
+fn run(mut args: Args) -> Result<bool> {
     // Check if all the flags are false
-    let are_all_flags_false = [args.words, args.bytes, args.chars, args.lines]
-        // Create an iterator.
-        .iter()
-        // Compare to &false because the values are references.
-        .all(|v| v == &false);
+    let are_all_flags_false = [
+        args.words,
+        args.bytes,
+        args.chars,
+        args.width,
+        args.max_line_length,
+        args.lines,
+    ]
+    // Create an iterator.
+    .iter()
+    // Compare to &false because the values are references.
+    .all(|v| v == &false);
 
     // Assign default settings if all flags are false.
     if are_all_flags_false {
@@ -64,71 +113,149 @@ fn run(mut args: Args) -> Result<()> {
         args.bytes = true;
     }
 
-    // Initialize total counters.
-    let mut total_lines = 0;
-    let mut total_words = 0;
-    let mut total_bytes = 0;
-    let mut total_chars = 0;
+    // Replace the positional files with the names listed in the NUL-separated source.
+    let mut has_invalid_name = false;
+    if let Some(source) = &args.files0_from {
+        let filehandle = open_input(source)?;
+        (args.files, has_invalid_name) = read_file_list(filehandle, source)?;
+    }
+
+    // Gather the counts of every file before printing anything, because the column width depends
+    // on the largest count. Only the per-file summaries are held, never the file contents.
+    let mut file_infos = vec![];
+    let mut has_unsized_input = false;
 
     for filename in &args.files {
-        match open_input_source(filename) {
+        match open_input(filename) {
             Err(e) => {
-                eprintln!("{filename}: {e}")
+                eprintln!("{e}")
             }
             Ok(filehandle) => {
                 let file_info = get_file_info(filehandle)?;
 
-                println!(
-                    "{}{}{}{}{}",
-                    format_field(file_info.line_count, args.lines),
-                    format_field(file_info.word_count, args.words),
-                    format_field(file_info.byte_count, args.bytes),
-                    format_field(file_info.char_count, args.chars),
-                    if filename == "-" {
-                        String::from("")
-                    } else {
-                        format!(" {filename}")
-                    },
-                );
-
-                // Update total counters.
-                total_lines += file_info.line_count;
-                total_words += file_info.word_count;
-                total_bytes += file_info.byte_count;
-                total_chars += file_info.char_count;
+                // Point at the line to look into without changing the report on STDOUT.
+                if args.show_longest_line {
+                    eprintln!(
+                        "{filename}: longest line {} ({} columns)",
+                        file_info.longest_line_number, file_info.max_line_length
+                    );
+                }
+
+                has_unsized_input |= !is_regular_file(filename);
+                file_infos.push((filename, file_info))
             }
         }
     }
 
-    let should_print_totals = args.files.len() > 1;
+    // Buffer the output because there is a write for every file.
+    print_report(
+        &file_infos,
+        &args,
+        has_unsized_input,
+        BufWriter::new(io::stdout().lock()),
+    )?;
+
+    Ok(!has_invalid_name)
+}
+
+// Returns whether the input is a regular file. STDIN is one only when it is redirected from a
+// regular file, rather than a pipe or a terminal.
+fn is_regular_file(filename: &str) -> bool {
+    let path = if filename == "-" {
+        "/dev/stdin"
+    } else {
+        filename
+    };
+    fs::metadata(path).is_ok_and(|metadata| metadata.is_file())
+}
+
+// Prints a row of counts for each file, followed by the total line when there is more than one
+// input file. An unsized input is one that is not a regular file, such as a pipe.
+fn print_report(
+    file_infos: &[(&String, FileInfo)],
+    args: &Args,
+    has_unsized_input: bool,
+    mut writer: impl Write,
+) -> Result<()> {
+    // Add up the counts for the total line. The maximum line length is the maximum of all files.
+    let total = file_infos.iter().fold(
+        FileInfo {
+            line_count: 0,
+            word_count: 0,
+            byte_count: 0,
+            char_count: 0,
+            display_width: 0,
+            max_line_length: 0,
+            longest_line_number: 0,
+        },
+        |total, (_, file_info)| FileInfo {
+            line_count: total.line_count + file_info.line_count,
+            word_count: total.word_count + file_info.word_count,
+            byte_count: total.byte_count + file_info.byte_count,
+            char_count: total.char_count + file_info.char_count,
+            display_width: total.display_width + file_info.display_width,
+            max_line_length: total.max_line_length.max(file_info.max_line_length),
+            // A line number is meaningless across files.
+            longest_line_number: 0,
+        },
+    );
+
+    // The total is never less than any single count, so it determines the width of every row.
+    // Like GNU wc, the columns are at least 7 wide when an input is unsized, unless there is only
+    // a single count of a single input to print.
+    let mut width = column_width(&total, args);
+    let is_single_count = file_infos.len() == 1 && selected_counts(&total, args).len() == 1;
+    if has_unsized_input && !is_single_count {
+        width = width.max(7);
+    }
+
+    for (filename, file_info) in file_infos {
+        writeln!(
+            writer,
+            "{}{}",
+            format_counts(file_info, args, width),
+            if *filename == "-" {
+                String::from("")
+            } else {
+                format!(" {filename}")
+            },
+        )?;
+    }
+
+    let should_print_totals = match args.total {
+        TotalMode::Auto => args.files.len() > 1,
+        TotalMode::Always => true,
+        TotalMode::Never => false,
+    };
 
     if should_print_totals {
-        println!(
-            "{}{}{}{} total",
-            format_field(total_lines, args.lines),
-            format_field(total_words, args.words),
-            format_field(total_bytes, args.bytes),
-            format_field(total_chars, args.chars),
-        )
+        writeln!(writer, "{} total", format_counts(&total, args, width))?;
     }
 
+    writer.flush()?;
+
     Ok(())
 }
 
-// Accepts a filename and returns either an error or a boxed value that implements the BufRead
-// trait.
-// - The return type includes the dyn keyword to say that the return type's trait is dynamically
-// dispatched. This allows us to abstract the idea of the input source.
-// - The return type is placed into a Box. which is a way to store a value on the heap. The
-// compiler does not have enough information from dyn BufRead to know the size of the return type.
-// If a variable does not have a fixed known size, then Rust cannot store it on the stack. The
-// solution is to instead allocate memory on the heap by putting the return value into a Box, which
-// is a pointer with a known size.
-fn open_input_source(filename: &str) -> Result<Box<dyn BufRead>> {
-    match filename {
-        "-" => Ok(Box::new(BufReader::new(io::stdin()))),
-        _ => Ok(Box::new(BufReader::new(File::open(filename)?))),
+// Reads the NUL-separated filenames for --files0-from, and returns them with whether any name
+// was empty. Like GNU wc, an empty name is reported with its position in the list and skipped. A
+// trailing NUL does not produce an empty name.
+fn read_file_list(filehandle: impl BufRead, source: &str) -> Result<(Vec<String>, bool)> {
+    let mut filenames = vec![];
+    let mut has_invalid_name = false;
+
+    for (index, filename) in filehandle.split(b'\0').enumerate() {
+        let filename = filename?;
+
+        if filename.is_empty() {
+            eprintln!("{source}:{}: invalid zero-length file name", index + 1);
+            has_invalid_name = true;
+        } else {
+            filenames.push(String::from_utf8_lossy(&filename).into_owned());
+        }
     }
+
+    Ok((filenames, has_invalid_name))
 }
 
 fn get_file_info(mut filehandle: impl BufRead) -> Result<FileInfo> {
@@ -137,6 +264,9 @@ fn get_file_info(mut filehandle: impl BufRead) -> Result<FileInfo> {
     let mut word_count = 0;
     let mut byte_count = 0;
     let mut char_count = 0;
+    let mut display_width = 0;
+    let mut max_line_length = 0;
+    let mut longest_line_number = 0;
 
     // Create a mutable buffer to hold each line of text.
     let mut line_buffer = String::new();
@@ -154,8 +284,16 @@ fn get_file_info(mut filehandle: impl BufRead) -> Result<FileInfo> {
 
         byte_count += bytes_read;
         line_count += 1;
-        word_count += line_buffer.split_whitespace().count();
+        word_count += count_words(&line_buffer);
         char_count += line_buffer.chars().count();
+        display_width += line_display_width(&line_buffer);
+
+        // Remember the first of the longest lines.
+        let length = line_length(&line_buffer);
+        if length > max_line_length || longest_line_number == 0 {
+            max_line_length = length;
+            longest_line_number = line_count;
+        }
 
         // Clear the line buffer for the next line of text.
         line_buffer.clear();
@@ -166,16 +304,89 @@ fn get_file_info(mut filehandle: impl BufRead) -> Result<FileInfo> {
         word_count,
         byte_count,
         char_count,
+        display_width,
+        max_line_length,
+        longest_line_number,
     })
 }
 
-// Format the values into a right-justified field eight characters wide.
-fn format_field(value: usize, show: bool) -> String {
-    if show {
-        format!("{:>8}", value)
-    } else {
-        String::from("")
+// Measures the terminal columns a line of text takes. Control characters such as the line ending
+// have no width.
+fn line_display_width(text: &str) -> usize {
+    text.chars()
+        .map(|c| UnicodeWidthChar::width(c).unwrap_or(0))
+        .sum()
+}
+
+// Measures a line like GNU wc -L: tabs advance to the next multiple of eight columns, and a
+// carriage return or form feed starts over from the first column.
+fn line_length(text: &str) -> usize {
+    let mut max_position = 0;
+    let mut position = 0;
+
+    for c in text.chars() {
+        match c {
+            '\n' | '\r' | '\x0c' => {
+                max_position = max_position.max(position);
+                position = 0;
+            }
+            '\t' => position += 8 - position % 8,
+            _ => position += UnicodeWidthChar::width(c).unwrap_or(0),
+        }
     }
+
+    max_position.max(position)
+}
+
+// Counts the words like GNU wc in the POSIX locale, where a word is a non-empty sequence of
+// characters delimited by the ASCII white space of isspace(3). Unlike str::split_whitespace, the
+// Unicode spaces such as U+00A0 do not break words.
+fn count_words(text: &str) -> usize {
+    text.split(is_word_separator)
+        .filter(|word| !word.is_empty())
+        .count()
+}
+
+// Space, horizontal tab, newline, vertical tab, form feed, and carriage return.
+fn is_word_separator(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\x0b' | '\x0c' | '\r')
+}
+
+// Select the counts to show, in the order GNU wc prints them.
+fn selected_counts(file_info: &FileInfo, args: &Args) -> Vec<usize> {
+    [
+        (file_info.line_count, args.lines),
+        (file_info.word_count, args.words),
+        (file_info.byte_count, args.bytes),
+        (file_info.char_count, args.chars),
+        (file_info.display_width, args.width),
+        (file_info.max_line_length, args.max_line_length),
+    ]
+    .into_iter()
+    .filter_map(|(value, show)| show.then_some(value))
+    .collect()
+}
+
+// Like GNU wc, size the columns to the number of digits in the largest count shown.
+fn column_width(file_info: &FileInfo, args: &Args) -> usize {
+    selected_counts(file_info, args)
+        .into_iter()
+        .max()
+        .map_or(1, |value| value.to_string().len())
+}
+
+// Format the selected counts into right-justified fields separated by a space.
+fn format_counts(file_info: &FileInfo, args: &Args, width: usize) -> String {
+    selected_counts(file_info, args)
+        .iter()
+        .map(|value| format_field(*value, width))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Format the value into a right-justified field of the given width.
+fn format_field(value: usize, width: usize) -> String {
+    format!("{:>width$}", value)
 }
 
 // Unit tests
@@ -204,19 +415,311 @@ mod tests {
                 word_count: 10,
                 char_count: 48,
                 byte_count: 48,
+                display_width: 45,
+                max_line_length: 23,
+                longest_line_number: 1,
             }
         );
     }
 
     #[test]
-    fn test_format_field() {
-        // Should return the empty string when show is false.
-        assert_eq!(format_field(1, false), "");
+    fn test_get_file_info_crlf() {
+        // The expected word counts were taken from GNU `wc -w`, which treats a carriage return as
+        // white space, so a trailing "\r" never joins or creates a word.
+        let cases = [
+            ("one two\r\nthree\r\n", 3),
+            ("\r\n\r\n", 0),
+            ("a\rb\r\n", 2),
+            ("word\r", 1),
+            (" lead\r\n  trail \r\n", 2),
+            ("I don't want the world.\r\nI just want your half.\r\n", 10),
+        ];
+
+        for (text, word_count) in cases {
+            let file_info = get_file_info(std::io::Cursor::new(text)).unwrap();
+            assert_eq!(file_info.word_count, word_count, "{text:?}");
+            assert_eq!(file_info.byte_count, text.len(), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn test_count_words() {
+        // The expected word counts were taken from GNU `wc -w` with LC_ALL=C.
+        let cases = [
+            ("page one\x0cpage two\n", 4),
+            ("\x0c\x0c\n", 0),
+            ("a\x0bb\x0b\x0bc", 3),
+            ("\x0b lead\x0c\ttrail \x0b\r\n", 2),
+            ("non\u{a0}breaking space\n", 2),
+            ("", 0),
+        ];
+
+        for (text, word_count) in cases {
+            assert_eq!(count_words(text), word_count, "{text:?}");
+            let file_info = get_file_info(std::io::Cursor::new(text)).unwrap();
+            assert_eq!(file_info.word_count, word_count, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn test_line_display_width() {
+        // ASCII takes one column per character, and the line ending takes none.
+        assert_eq!(line_display_width("hello\r\n"), 5);
+
+        // The combining acute accent takes no column of its own.
+        assert_eq!(line_display_width("cafe\u{301}\n"), 4);
+
+        // A CJK character takes two columns.
+        assert_eq!(line_display_width("日本\n"), 4);
+        assert_eq!(line_display_width("a日b"), 4);
+
+        // The width is summed over the lines, while -m still counts the scalar values.
+        let file_info = get_file_info(std::io::Cursor::new("cafe\u{301}\n日本\n")).unwrap();
+        assert_eq!(file_info.display_width, 8);
+        assert_eq!(file_info.char_count, 9);
+
+        let args = Args::parse_from(["wcr", "-m", "--width"]);
+        assert_eq!(format_counts(&file_info, &args, 1), "9 8");
+    }
+
+    #[test]
+    fn test_line_length() {
+        assert_eq!(line_length("hello\n"), 5);
+        assert_eq!(line_length("a\tb\n"), 9);
+        assert_eq!(line_length("日本\r\n"), 4);
+        assert_eq!(line_length("long line\rshort\n"), 9);
+    }
+
+    #[test]
+    fn test_longest_line() {
+        let text = "fn main() {\n    let message = \"a rather long line of text\";\n}\n\n";
+        let file_info = get_file_info(std::io::Cursor::new(text)).unwrap();
+        assert_eq!(file_info.longest_line_number, 2);
+        assert_eq!(file_info.max_line_length, 47);
+
+        // The first of the lines with the same length is reported.
+        let file_info = get_file_info(std::io::Cursor::new("ab\ncd\n")).unwrap();
+        assert_eq!(file_info.longest_line_number, 1);
+
+        let file_info = get_file_info(std::io::Cursor::new("")).unwrap();
+        assert_eq!(file_info.longest_line_number, 0);
+    }
 
+    #[test]
+    fn test_format_field() {
         // Formatting for a single-digit number.
-        assert_eq!(format_field(3, true), "       3");
+        assert_eq!(format_field(3, 1), "3");
+
+        // Formatting for a number narrower than the field.
+        assert_eq!(format_field(3, 8), "       3");
 
         // Formatting for a double-digit number.
-        assert_eq!(format_field(10, true), "      10");
+        assert_eq!(format_field(10, 8), "      10");
+    }
+
+    #[test]
+    fn test_format_counts_small_file() {
+        let args = Args::parse_from(["wcr", "-l", "-w", "-c"]);
+        let file_info = get_file_info(std::io::Cursor::new("hello world\n")).unwrap();
+
+        // The columns are as narrow as the byte count, which is the largest.
+        let width = column_width(&file_info, &args);
+        assert_eq!(width, 2);
+        assert_eq!(format_counts(&file_info, &args, width), " 1  2 12");
+    }
+
+    #[test]
+    fn test_format_counts_large_count() {
+        let args = Args::parse_from(["wcr", "-l", "-m"]);
+        let small = FileInfo {
+            line_count: 1,
+            word_count: 2,
+            byte_count: 12,
+            char_count: 12,
+            display_width: 11,
+            max_line_length: 11,
+            longest_line_number: 1,
+        };
+        let total = FileInfo {
+            line_count: 1001,
+            word_count: 30000,
+            byte_count: 123456,
+            char_count: 123450,
+            display_width: 123000,
+            max_line_length: 80,
+            longest_line_number: 0,
+        };
+
+        // The hidden byte count does not widen the columns.
+        let width = column_width(&total, &args);
+        assert_eq!(width, 6);
+        assert_eq!(format_counts(&small, &args, width), "     1     12");
+        assert_eq!(format_counts(&total, &args, width), "  1001 123450");
+
+        // A zero count still takes one column.
+        let no_counts = Args::parse_from(["wcr", "-l"]);
+        assert_eq!(
+            column_width(
+                &FileInfo {
+                    line_count: 0,
+                    ..small
+                },
+                &no_counts
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn test_read_file_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let fox = dir.path().join("fox.txt");
+        let empty = dir.path().join("empty.txt");
+        std::fs::write(&fox, "The quick brown fox\n").unwrap();
+        std::fs::write(&empty, "").unwrap();
+
+        // List the two files the way `find -print0` does, with an empty name in between.
+        let list = format!("{}\0\0{}\0", fox.display(), empty.display());
+        let (filenames, has_invalid_name) =
+            read_file_list(std::io::Cursor::new(list), "-").unwrap();
+        assert_eq!(
+            filenames,
+            [fox.display().to_string(), empty.display().to_string()]
+        );
+        assert!(has_invalid_name);
+
+        // Each listed file is counted in turn, followed by the total.
+        let file_infos: Vec<_> = filenames
+            .iter()
+            .map(|filename| {
+                (
+                    filename,
+                    get_file_info(open_input(filename).unwrap()).unwrap(),
+                )
+            })
+            .collect();
+        let mut argv = vec!["wcr", "-l", "-w", "-c"];
+        argv.extend(filenames.iter().map(String::as_str));
+        assert_eq!(
+            report(&argv, &file_infos),
+            format!(
+                " 1  4 20 {}\n 0  0  0 {}\n 1  4 20 total\n",
+                fox.display(),
+                empty.display()
+            )
+        );
+
+        // A list without empty names is valid.
+        let list = format!("{}\0", fox.display());
+        let (_, has_invalid_name) = read_file_list(std::io::Cursor::new(list), "-").unwrap();
+        assert!(!has_invalid_name);
+    }
+
+    // Runs print_report over the given counts and returns the output. Here, STDIN stands for a
+    // pipe, and the named files are regular files.
+    fn report(argv: &[&str], file_infos: &[(&String, FileInfo)]) -> String {
+        let has_unsized_input = file_infos.iter().any(|(filename, _)| *filename == "-");
+        let mut output = vec![];
+        print_report(
+            file_infos,
+            &Args::parse_from(argv),
+            has_unsized_input,
+            &mut output,
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_print_report() {
+        let fox = String::from("fox.txt");
+        let stdin = String::from("-");
+        let counts = |line_count, word_count, byte_count| FileInfo {
+            line_count,
+            word_count,
+            byte_count,
+            char_count: byte_count,
+            display_width: byte_count,
+            max_line_length: byte_count,
+            longest_line_number: 1,
+        };
+
+        // A single file has no total line, and STDIN has no label. Like GNU wc, a pipe has no
+        // size to go by, so its columns are 7 wide, unless it has only a single count.
+        assert_eq!(
+            report(&["wcr", "-l", "-w", "-c"], &[(&stdin, counts(1, 4, 20))]),
+            "      1       4      20\n"
+        );
+        assert_eq!(report(&["wcr", "-l"], &[(&stdin, counts(1, 4, 20))]), "1\n");
+        assert_eq!(
+            report(
+                &["wcr", "-l", "-", "fox.txt"],
+                &[(&stdin, counts(1, 4, 20)), (&fox, counts(1, 4, 20))]
+            ),
+            "      1\n      1 fox.txt\n      2 total\n"
+        );
+
+        // The total line widens the columns of every row.
+        assert_eq!(
+            report(
+                &["wcr", "-l", "-w", "-c", "fox.txt", "fox.txt"],
+                &[(&fox, counts(1, 4, 20)), (&fox, counts(3, 30, 90))]
+            ),
+            "  1   4  20 fox.txt\n  3  30  90 fox.txt\n  4  34 110 total\n"
+        );
+
+        // Only the selected counts are printed.
+        assert_eq!(
+            report(
+                &["wcr", "-w", "fox.txt", "fox.txt"],
+                &[(&fox, counts(1, 4, 20)), (&fox, counts(1, 4, 20))]
+            ),
+            "4 fox.txt\n4 fox.txt\n8 total\n"
+        );
+    }
+
+    #[test]
+    fn test_print_report_total() {
+        let fox = String::from("fox.txt");
+        let counts = FileInfo {
+            line_count: 1,
+            word_count: 4,
+            byte_count: 20,
+            char_count: 20,
+            display_width: 19,
+            max_line_length: 19,
+            longest_line_number: 1,
+        };
+        let one_file = [(&fox, counts)];
+        let two_files = [
+            (&fox, get_file_info(std::io::Cursor::new("fox\n")).unwrap()),
+            (&fox, get_file_info(std::io::Cursor::new("dog\n")).unwrap()),
+        ];
+
+        for (mode, one_file_output, two_files_output) in [
+            ("auto", "1 fox.txt\n", "1 fox.txt\n1 fox.txt\n2 total\n"),
+            (
+                "always",
+                "1 fox.txt\n1 total\n",
+                "1 fox.txt\n1 fox.txt\n2 total\n",
+            ),
+            ("never", "1 fox.txt\n", "1 fox.txt\n1 fox.txt\n"),
+        ] {
+            let total = format!("--total={mode}");
+            assert_eq!(
+                report(&["wcr", "-l", &total, "fox.txt"], &one_file),
+                one_file_output,
+                "{mode}"
+            );
+            assert_eq!(
+                report(&["wcr", "-l", &total, "fox.txt", "fox.txt"], &two_files),
+                two_files_output,
+                "{mode}"
+            );
+        }
+
+        // The default is auto.
+        assert_eq!(report(&["wcr", "-l", "fox.txt"], &one_file), "1 fox.txt\n");
+        assert!(Args::try_parse_from(["wcr", "--total=sometimes"]).is_err());
     }
 }